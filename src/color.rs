@@ -0,0 +1,154 @@
+use clap::ValueEnum;
+use ratatui::{buffer::Buffer, style::Color};
+
+/// How many colors the terminal can show.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum ColorDepth {
+    Auto,
+    #[value(name = "truecolor")]
+    TrueColor,
+    #[value(name = "256")]
+    Ansi256,
+    #[value(name = "16")]
+    Ansi16,
+}
+
+impl ColorDepth {
+    /// Resolves `Auto` from `COLORTERM` and `TERM`.
+    pub fn detect(self) -> Self {
+        if self != ColorDepth::Auto {
+            return self;
+        }
+        let colorterm = std::env::var("COLORTERM").unwrap_or_default();
+        let term = std::env::var("TERM").unwrap_or_default();
+        if colorterm == "truecolor" || colorterm == "24bit" {
+            ColorDepth::TrueColor
+        } else if term.contains("256color") {
+            ColorDepth::Ansi256
+        } else {
+            ColorDepth::Ansi16
+        }
+    }
+
+    /// Downgrades every cell of a rendered frame to what the terminal supports.
+    pub fn apply(self, buf: &mut Buffer) {
+        if matches!(self, ColorDepth::TrueColor | ColorDepth::Auto) {
+            return;
+        }
+        for cell in buf.content.iter_mut() {
+            cell.fg = self.adapt(cell.fg);
+            cell.bg = self.adapt(cell.bg);
+        }
+    }
+
+    pub fn adapt(self, c: Color) -> Color {
+        match (self, c) {
+            (ColorDepth::Ansi256, Color::Rgb(r, g, b)) => Color::Indexed(rgb_to_256(r, g, b)),
+            (ColorDepth::Ansi16, Color::Rgb(r, g, b)) => nearest_16(r, g, b),
+            (ColorDepth::Ansi16, Color::Indexed(i)) if i < 16 => ANSI16[i as usize].0,
+            (ColorDepth::Ansi16, Color::Indexed(i)) => {
+                let (r, g, b) = indexed_to_rgb(i);
+                nearest_16(r, g, b)
+            }
+            _ => c,
+        }
+    }
+}
+
+/// How bad an RTT or loss figure is, against `--warn`/`--crit` or `--loss-warn`/`--loss-crit`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    Ok,
+    Warn,
+    Crit,
+}
+
+/// Colors used for severity.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Palette {
+    /// Green, yellow and red
+    Default,
+    /// Blue, orange and purple (Okabe-Ito), with a distinct shape per level
+    Colorblind,
+}
+
+impl Palette {
+    pub fn color(self, s: Severity) -> Color {
+        match (self, s) {
+            (Palette::Default, Severity::Ok) => Color::Green,
+            (Palette::Default, Severity::Warn) => Color::Yellow,
+            (Palette::Default, Severity::Crit) => Color::Red,
+            (Palette::Colorblind, Severity::Ok) => Color::Rgb(0, 114, 178),
+            (Palette::Colorblind, Severity::Warn) => Color::Rgb(230, 159, 0),
+            (Palette::Colorblind, Severity::Crit) => Color::Rgb(204, 121, 167),
+        }
+    }
+
+    /// A shape for each level, so it isn't told apart by color alone; the default
+    /// palette uses none.
+    pub fn glyph(self, s: Severity) -> Option<&'static str> {
+        match (self, s) {
+            (Palette::Default, _) => None,
+            (Palette::Colorblind, Severity::Ok) => Some("●"),
+            (Palette::Colorblind, Severity::Warn) => Some("▲"),
+            (Palette::Colorblind, Severity::Crit) => Some("◆"),
+        }
+    }
+}
+
+// The xterm defaults for the 16 named colors.
+const ANSI16: [(Color, (u8, u8, u8)); 16] = [
+    (Color::Black, (0, 0, 0)),
+    (Color::Red, (205, 0, 0)),
+    (Color::Green, (0, 205, 0)),
+    (Color::Yellow, (205, 205, 0)),
+    (Color::Blue, (0, 0, 238)),
+    (Color::Magenta, (205, 0, 205)),
+    (Color::Cyan, (0, 205, 205)),
+    (Color::Gray, (229, 229, 229)),
+    (Color::DarkGray, (127, 127, 127)),
+    (Color::LightRed, (255, 0, 0)),
+    (Color::LightGreen, (0, 255, 0)),
+    (Color::LightYellow, (255, 255, 0)),
+    (Color::LightBlue, (92, 92, 255)),
+    (Color::LightMagenta, (255, 0, 255)),
+    (Color::LightCyan, (0, 255, 255)),
+    (Color::White, (255, 255, 255)),
+];
+
+const CUBE: [u8; 6] = [0, 95, 135, 175, 215, 255];
+
+fn rgb_to_256(r: u8, g: u8, b: u8) -> u8 {
+    // Greys map better onto the 24-step ramp than onto the cube's diagonal.
+    if r == g && g == b {
+        return match r {
+            0..=7 => 16,
+            248..=255 => 231,
+            v => 232 + (v - 8) / 10,
+        };
+    }
+    let level = |v: u8| CUBE.iter().enumerate().min_by_key(|(_, &l)| l.abs_diff(v)).map(|(i, _)| i as u8).unwrap_or(0);
+    16 + 36 * level(r) + 6 * level(g) + level(b)
+}
+
+fn indexed_to_rgb(i: u8) -> (u8, u8, u8) {
+    match i {
+        0..=15 => ANSI16[i as usize].1,
+        16..=231 => {
+            let i = i - 16;
+            (CUBE[(i / 36) as usize], CUBE[(i / 6 % 6) as usize], CUBE[(i % 6) as usize])
+        }
+        _ => {
+            let v = 8 + (i - 232) * 10;
+            (v, v, v)
+        }
+    }
+}
+
+fn nearest_16(r: u8, g: u8, b: u8) -> Color {
+    let dist = |(cr, cg, cb): (u8, u8, u8)| {
+        let d = |a: u8, b: u8| (a as i32 - b as i32).pow(2);
+        d(r, cr) + d(g, cg) + d(b, cb)
+    };
+    ANSI16.iter().min_by_key(|(_, rgb)| dist(*rgb)).map(|(c, _)| *c).unwrap_or(Color::Reset)
+}
@@ -0,0 +1,132 @@
+use std::env;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::time::Duration;
+
+use anyhow::Result;
+
+use crate::dns;
+use crate::pinger::Resolve;
+
+// Used for the resolver check when no host is given.
+const TEST_HOST: &str = "example.com";
+
+/// Prints what rgping can find out about its environment, for bug reports.
+pub async fn run(host: Option<&str>, ping_bin: &str, resolve: (Resolve, Duration), channel_size: usize) -> Result<()> {
+    println!("rgping {}", env!("CARGO_PKG_VERSION"));
+    row("os", format!("{} ({})", env::consts::OS, env::consts::ARCH));
+
+    match find_in_path(ping_bin) {
+        Some(p) if is_executable(&p) => row("ping binary", format!("{} (executable)", p.display())),
+        Some(p) => row("ping binary", format!("{} (NOT executable)", p.display())),
+        None => row("ping binary", "not found in PATH"),
+    }
+    row("unprivileged ICMP", unprivileged_icmp());
+
+    let host = host.unwrap_or(TEST_HOST);
+    match dns::resolve(host).await {
+        Ok(r) => row(&format!("resolve {host}"), format!("{} in {:.1}ms", r.addr, r.took.as_secs_f64() * 1000.0)),
+        Err(e) => row(&format!("resolve {host}"), format!("{e:#}")),
+    }
+
+    row("resolution", match resolve {
+        (Resolve::Once, _) => "once at startup".to_string(),
+        (Resolve::PerProbe, _) => "before every probe".to_string(),
+        (Resolve::Periodic, every) => format!("every {}", humantime::format_duration(every)),
+    });
+    // Skips only happen in a running session, which reports them and the high-water
+    // mark when it exits.
+    row("channel size", format!("{channel_size} samples (a session that skips any reports it on exit)"));
+
+    let var = |k: &str| env::var(k).unwrap_or_else(|_| "-".into());
+    let size = crossterm::terminal::size()
+        .map(|(w, h)| format!("{w}x{h}"))
+        .unwrap_or_else(|_| "not a terminal".into());
+    row("terminal", format!("TERM={} COLORTERM={}, {size}", var("TERM"), var("COLORTERM")));
+    Ok(())
+}
+
+/// How full the channel feeding the UI has got, and how many samples it had to drop.
+#[derive(Debug)]
+pub struct ChannelStats {
+    pub capacity: usize,
+    high_water: AtomicUsize,
+    dropped: AtomicU64,
+}
+
+impl ChannelStats {
+    pub fn new(capacity: usize) -> Self {
+        Self { capacity, high_water: AtomicUsize::new(0), dropped: AtomicU64::new(0) }
+    }
+
+    /// Notes that `queued` samples were waiting in the channel.
+    pub fn record(&self, queued: usize) {
+        self.high_water.fetch_max(queued, Ordering::Relaxed);
+    }
+
+    pub fn record_drop(&self) {
+        self.dropped.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn high_water(&self) -> usize {
+        self.high_water.load(Ordering::Relaxed)
+    }
+
+    pub fn dropped(&self) -> u64 {
+        self.dropped.load(Ordering::Relaxed)
+    }
+}
+
+fn row(label: &str, value: impl std::fmt::Display) {
+    println!("{:<20}{value}", format!("{label}:"));
+}
+
+fn find_in_path(bin: &str) -> Option<PathBuf> {
+    if bin.contains(std::path::MAIN_SEPARATOR) {
+        return Some(PathBuf::from(bin)).filter(|p| p.is_file());
+    }
+    env::split_paths(&env::var_os("PATH")?)
+        .map(|dir| dir.join(bin))
+        .find(|p| p.is_file())
+}
+
+#[cfg(unix)]
+fn is_executable(p: &Path) -> bool {
+    use std::os::unix::fs::PermissionsExt;
+    p.metadata().is_ok_and(|m| m.permissions().mode() & 0o111 != 0)
+}
+
+#[cfg(not(unix))]
+fn is_executable(p: &Path) -> bool {
+    p.is_file()
+}
+
+/// Linux allows ICMP echo sockets without privileges for groups in `ping_group_range`.
+#[cfg(target_os = "linux")]
+fn unprivileged_icmp() -> String {
+    let Ok(range) = std::fs::read_to_string("/proc/sys/net/ipv4/ping_group_range") else {
+        return "unknown (can't read ping_group_range)".into();
+    };
+    let mut bounds = range.split_whitespace().filter_map(|v| v.parse::<u64>().ok());
+    let (Some(lo), Some(hi)) = (bounds.next(), bounds.next()) else {
+        return format!("unknown (unexpected ping_group_range `{}`)", range.trim());
+    };
+    // The effective gid is the second of "Gid:" (real, effective, saved, fs); the
+    // kernel also accepts any supplementary group listed under "Groups:".
+    let status = std::fs::read_to_string("/proc/self/status").unwrap_or_default();
+    let field = |key: &str| status.lines().find_map(|l| l.strip_prefix(key)).unwrap_or_default();
+    let Some(gid) = field("Gid:").split_whitespace().nth(1).and_then(|g| g.parse::<u64>().ok()) else {
+        return format!("unknown (ping_group_range {lo}-{hi})");
+    };
+    let groups = field("Groups:").split_whitespace().filter_map(|g| g.parse::<u64>().ok());
+    match std::iter::once(gid).chain(groups).find(|g| (lo..=hi).contains(g)) {
+        Some(g) if g == gid => format!("yes (gid {gid} in ping_group_range {lo}-{hi})"),
+        Some(g) => format!("yes (supplementary group {g} in ping_group_range {lo}-{hi})"),
+        None => format!("no (gid {gid} and supplementary groups outside ping_group_range {lo}-{hi})"),
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+fn unprivileged_icmp() -> String {
+    "unknown on this OS".into()
+}
@@ -0,0 +1,40 @@
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use std::time::Duration;
+
+use anyhow::{anyhow, Context, Result};
+use tokio::{net::{lookup_host, UdpSocket}, time::Instant};
+
+/// The address a host name resolved to and how long the lookup took.
+#[derive(Debug, Clone, Copy)]
+pub struct Resolved {
+    pub addr: IpAddr,
+    pub took: Duration,
+}
+
+pub async fn resolve(host: &str) -> Result<Resolved> {
+    let start = Instant::now();
+    let addr = lookup_host((host, 0))
+        .await
+        .with_context(|| format!("failed to resolve `{host}`"))?
+        .next()
+        .ok_or_else(|| anyhow!("`{host}` resolved to no addresses"))?
+        .ip();
+    Ok(Resolved { addr, took: start.elapsed() })
+}
+
+/// The local address the kernel would send from to reach `dest`. Connecting a UDP
+/// socket only runs the route lookup; nothing is sent.
+pub async fn source_for(dest: IpAddr) -> Result<IpAddr> {
+    let any: IpAddr = if dest.is_ipv6() { Ipv6Addr::UNSPECIFIED.into() } else { Ipv4Addr::UNSPECIFIED.into() };
+    let sock = UdpSocket::bind((any, 0)).await?;
+    sock.connect((dest, 9)).await.with_context(|| format!("no route to {dest}"))?;
+    Ok(sock.local_addr()?.ip())
+}
+
+/// The PTR name for `addr`. getnameinfo blocks, so it runs on the blocking pool.
+pub async fn reverse(addr: IpAddr) -> Result<String> {
+    let name = tokio::task::spawn_blocking(move || dns_lookup::lookup_addr(&addr))
+        .await?
+        .with_context(|| format!("no PTR record for {addr}"))?;
+    Ok(name)
+}
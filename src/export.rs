@@ -0,0 +1,511 @@
+use std::collections::hash_map::RandomState;
+use std::fs::{File, OpenOptions};
+use std::hash::BuildHasher;
+use std::io::{BufWriter, Write};
+use std::net::IpAddr;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use anyhow::{Context, Result};
+use chrono::{DateTime, Local, SecondsFormat};
+use clap::ValueEnum;
+
+use crate::pinger::{Flags, IcmpError, Outcome, PingSample};
+
+const FLUSH_EVERY: Duration = Duration::from_secs(1);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+#[value(rename_all = "snake_case")]
+pub enum TsFormat {
+    Rfc3339,
+    Epoch,
+    EpochMs,
+}
+
+impl TsFormat {
+    pub fn format(self, ts: SystemTime) -> String {
+        let since_epoch = ts.duration_since(UNIX_EPOCH).unwrap_or_default();
+        match self {
+            TsFormat::Rfc3339 => DateTime::<Local>::from(ts).to_rfc3339_opts(SecondsFormat::Millis, false),
+            TsFormat::Epoch => format!("{:.3}", since_epoch.as_secs_f64()),
+            TsFormat::EpochMs => since_epoch.as_millis().to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct CsvFormat {
+    pub sep: char,
+    pub ts: TsFormat,
+    pub bucket: Option<Duration>, // write one aggregate row per bucket instead of each sample
+}
+
+/// Parses a `--csv-sep` value: a single character, or `tab`/`\t` for a tab.
+pub fn parse_sep(s: &str) -> std::result::Result<char, String> {
+    let sep = match s {
+        "tab" | "\\t" => '\t',
+        _ => {
+            let mut chars = s.chars();
+            match (chars.next(), chars.next()) {
+                (Some(c), None) => c,
+                _ => return Err(format!("expected a single character, got `{s}`")),
+            }
+        }
+    };
+    // These show up inside timestamps and RTT values and would split fields.
+    if sep.is_ascii_digit() || matches!(sep, '\n' | '\r' | '.' | '-' | '+' | ':') {
+        return Err(format!("`{}` cannot be used as a separator", sep.escape_default()));
+    }
+    Ok(sep)
+}
+
+/// Appends samples to a CSV file as they arrive, flushing at most once per second.
+pub struct CsvAppender {
+    out: BufWriter<File>,
+    fmt: CsvFormat,
+    bucket: Option<Bucket>,
+    last_flush: Instant,
+    summary: Arc<Mutex<Summary>>, // where the TUI leaves its notes
+    notes_seen: usize,
+    pending: Vec<Note>, // notes held back until their bucket's row is out
+}
+
+/// Totals for the `--export-bucket` interval being filled.
+struct Bucket {
+    start: SystemTime,
+    sent: u64,
+    received: u64,
+    sum: f64,
+    min: f64,
+    max: f64,
+}
+
+impl Bucket {
+    fn new(start: SystemTime) -> Self {
+        Self { start, sent: 0, received: 0, sum: 0.0, min: f64::INFINITY, max: f64::NEG_INFINITY }
+    }
+
+    fn add(&mut self, rtt: Option<f64>) {
+        self.sent += 1;
+        if let Some(v) = rtt {
+            self.received += 1;
+            self.sum += v;
+            self.min = self.min.min(v);
+            self.max = self.max.max(v);
+        }
+    }
+}
+
+/// The start of the bucket `ts` falls in; buckets are aligned to the epoch so runs line up.
+fn bucket_start(ts: SystemTime, len: Duration) -> SystemTime {
+    let since = ts.duration_since(UNIX_EPOCH).unwrap_or_default().as_millis();
+    let len = len.as_millis().max(1);
+    UNIX_EPOCH + Duration::from_millis((since - since % len) as u64)
+}
+
+impl CsvAppender {
+    pub fn open(path: &Path, fmt: CsvFormat, summary: Arc<Mutex<Summary>>) -> Result<Self> {
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .with_context(|| format!("failed to open `{}`", path.display()))?;
+        let is_new = file.metadata()?.len() == 0;
+
+        let mut out = BufWriter::new(file);
+        if is_new {
+            let sep = fmt.sep;
+            match fmt.bucket {
+                Some(_) => writeln!(
+                    out,
+                    "timestamp{sep}sent{sep}received{sep}min_ms{sep}avg_ms{sep}max_ms{sep}loss_pct{sep}note"
+                )?,
+                None => writeln!(out, "timestamp{sep}seq{sep}rtt_ms{sep}flags{sep}note")?,
+            }
+        }
+        Ok(Self { out, fmt, bucket: None, last_flush: Instant::now(), summary, notes_seen: 0, pending: Vec::new() })
+    }
+
+    pub fn write(&mut self, s: &PingSample) -> Result<()> {
+        // Notes are attached to a sample already shown, so they go ahead of this one.
+        self.write_notes()?;
+        if let Some(len) = self.fmt.bucket {
+            let start = bucket_start(s.ts, len);
+            if self.bucket.as_ref().is_some_and(|b| b.start != start) {
+                self.write_bucket()?;
+            }
+            self.bucket.get_or_insert_with(|| Bucket::new(start)).add(s.rtt_ms);
+            return Ok(());
+        }
+        let sep = self.fmt.sep;
+        let ts = self.fmt.ts.format(s.ts);
+        let rtt = s.rtt_ms.map(|v| v.to_string()).unwrap_or_default();
+        writeln!(self.out, "{ts}{sep}{}{sep}{rtt}{sep}{}{sep}", s.seq, s.flags)?;
+
+        if self.last_flush.elapsed() >= FLUSH_EVERY {
+            self.flush()?;
+        }
+        Ok(())
+    }
+
+    /// Writes the row for the bucket being filled, if any.
+    fn write_bucket(&mut self) -> Result<()> {
+        let Some(b) = self.bucket.take() else { return Ok(()) };
+        let sep = self.fmt.sep;
+        let ts = self.fmt.ts.format(b.start);
+        let ms = |v: f64| if b.received > 0 { format!("{v:.3}") } else { String::new() };
+        let loss = (b.sent - b.received) as f64 * 100.0 / b.sent as f64;
+        writeln!(
+            self.out,
+            "{ts}{sep}{}{sep}{}{sep}{}{sep}{}{sep}{}{sep}{loss:.1}{sep}",
+            b.sent, b.received, ms(b.min), ms(b.sum / b.received.max(1) as f64), ms(b.max),
+        )?;
+        // Notes taken in a later bucket wait for that bucket's row.
+        let len = self.fmt.bucket.unwrap_or_default();
+        let (due, later): (Vec<_>, Vec<_>) =
+            std::mem::take(&mut self.pending).into_iter().partition(|n| bucket_start(n.ts, len) <= b.start);
+        self.pending = later;
+        for note in &due {
+            self.write_note(note)?;
+        }
+        if self.last_flush.elapsed() >= FLUSH_EVERY {
+            self.flush()?;
+        }
+        Ok(())
+    }
+
+    /// Picks up notes added since the last call: written straight away, or after the
+    /// row of the bucket they were taken in.
+    fn write_notes(&mut self) -> Result<()> {
+        let new = match self.summary.lock() {
+            Ok(summary) => summary.notes[self.notes_seen..].to_vec(),
+            Err(_) => return Ok(()),
+        };
+        self.notes_seen += new.len();
+        if self.fmt.bucket.is_some() {
+            self.pending.extend(new);
+            return Ok(());
+        }
+        for note in &new {
+            self.write_note(note)?;
+        }
+        Ok(())
+    }
+
+    /// A row with only the timestamp, the seq (per-sample rows) and the note text.
+    fn write_note(&mut self, note: &Note) -> Result<()> {
+        let sep = self.fmt.sep;
+        let ts = self.fmt.ts.format(note.ts);
+        let text = csv_field(&note.text, sep);
+        match self.fmt.bucket {
+            Some(_) => writeln!(self.out, "{ts}{sep}{sep}{sep}{sep}{sep}{sep}{sep}{text}")?,
+            None => writeln!(self.out, "{ts}{sep}{}{sep}{sep}{sep}{text}", note.seq)?,
+        }
+        Ok(())
+    }
+
+    pub fn flush(&mut self) -> Result<()> {
+        self.out.flush()?;
+        self.last_flush = Instant::now();
+        Ok(())
+    }
+}
+
+impl Drop for CsvAppender {
+    /// Keeps the partly filled last bucket rather than dropping it on exit.
+    fn drop(&mut self) {
+        let _ = self.write_notes();
+        let _ = self.write_bucket();
+        // With no bucket open there was no row for pending notes to follow.
+        for note in std::mem::take(&mut self.pending) {
+            let _ = self.write_note(&note);
+        }
+    }
+}
+
+/// Replaces IP addresses with pseudonyms for `--anonymize`. The hash key is random per
+/// run, so an address keeps the same pseudonym for the whole run (and across clones)
+/// but can't be matched up with another run's. Without `--anonymize` it passes
+/// addresses through unchanged.
+#[derive(Debug, Clone, Default)]
+pub struct Anonymizer(Option<RandomState>);
+
+impl Anonymizer {
+    pub fn new(enabled: bool) -> Self {
+        Self(enabled.then(RandomState::new))
+    }
+
+    pub fn ip(&self, ip: IpAddr) -> String {
+        match &self.0 {
+            Some(key) => {
+                let family = if ip.is_ipv4() { "ip4" } else { "ip6" };
+                format!("{family}-{:08x}", key.hash_one(ip) as u32)
+            }
+            None => ip.to_string(),
+        }
+    }
+
+    /// A host as given on the command line: IP literals are replaced, names are kept.
+    pub fn host(&self, host: &str) -> String {
+        match host.parse::<IpAddr>() {
+            Ok(ip) => self.ip(ip),
+            Err(_) => host.to_string(),
+        }
+    }
+
+    pub fn icmp(&self, e: &IcmpError) -> String {
+        match e.from {
+            Some(from) => format!("{} from {}", e.reason, self.ip(from)),
+            None => e.reason.to_string(),
+        }
+    }
+}
+
+/// Whole-run totals for `--summary-json`, independent of the UI's sliding window.
+pub struct Summary {
+    host: String,
+    sent: u64,
+    rtts: Vec<f64>,
+    retried: u64,
+    duplicates: u64,
+    reordered: u64,
+    unreachable: u64, // ICMP errors instead of replies
+    separate_unreachable: bool, // --unreachable-as separate: they aren't loss
+    // The first `warmup` samples are left out; `warmed` counts those seen so far.
+    warmup: u64,
+    warmed: u64,
+    notes: Vec<Note>,
+    started: Instant,
+}
+
+/// A note typed into the TUI, attached to the newest sample at the time.
+#[derive(Debug, Clone)]
+pub struct Note {
+    pub seq: u64,
+    pub ts: SystemTime,
+    pub text: String,
+}
+
+impl Summary {
+    pub fn new(host: String, warmup: u64, separate_unreachable: bool) -> Self {
+        Self {
+            host,
+            sent: 0,
+            rtts: Vec::new(),
+            retried: 0,
+            duplicates: 0,
+            reordered: 0,
+            unreachable: 0,
+            separate_unreachable,
+            warmup,
+            warmed: 0,
+            notes: Vec::new(),
+            started: Instant::now(),
+        }
+    }
+
+    pub fn add_note(&mut self, note: Note) {
+        self.notes.push(note);
+    }
+
+    pub fn push(&mut self, s: &PingSample) {
+        if s.seq <= self.warmup {
+            self.warmed += 1;
+            return;
+        }
+        self.sent += 1;
+        if let Some(rtt) = s.rtt_ms {
+            self.rtts.push(rtt);
+        }
+        self.retried += s.flags.contains(Flags::RETRIED) as u64;
+        self.duplicates += s.flags.contains(Flags::DUPLICATE) as u64;
+        self.reordered += s.flags.contains(Flags::REORDERED) as u64;
+        self.unreachable += matches!(s.outcome, Outcome::Unreachable(_)) as u64;
+    }
+
+    fn sorted_rtts(&self) -> Vec<f64> {
+        let mut sorted = self.rtts.clone();
+        sorted.sort_by(f64::total_cmp);
+        sorted
+    }
+
+    /// Percent of probes without a reply, leaving out ICMP errors when those are
+    /// tallied separately (as the footer does).
+    pub fn loss(&self) -> f64 {
+        let mut lost = self.sent - self.rtts.len() as u64;
+        if self.separate_unreachable {
+            lost -= self.unreachable;
+        }
+        if self.sent == 0 { 0.0 } else { lost as f64 * 100.0 / self.sent as f64 }
+    }
+
+    pub fn p95(&self) -> Option<f64> {
+        nearest_rank(&self.sorted_rtts(), 95.0)
+    }
+
+    pub fn to_json(&self) -> String {
+        let sorted = self.sorted_rtts();
+        let n = sorted.len();
+        let received = n as u64;
+        let loss = self.loss();
+        let avg = (n > 0).then(|| sorted.iter().sum::<f64>() / n as f64);
+        let stddev = avg.filter(|_| n > 1).map(|mean| {
+            (sorted.iter().map(|v| (v - mean).powi(2)).sum::<f64>() / (n - 1) as f64).sqrt()
+        });
+        let pct = |p: f64| nearest_rank(&sorted, p);
+        let num = |v: Option<f64>| v.map(|v| format!("{v:.3}")).unwrap_or_else(|| "null".into());
+
+        format!(
+            "{{\"host\":{},\"sent\":{},\"received\":{received},\"loss\":{loss:.3},\"min\":{},\"avg\":{},\"max\":{},\
+             \"stddev\":{},\"p50\":{},\"p95\":{},\"p99\":{},\"retried\":{},\"duplicates\":{},\"reordered\":{},\
+             \"unreachable\":{},\"warmup_excluded\":{},\"notes\":[{}],\"duration\":{:.3}}}",
+            json_str(&self.host),
+            self.sent,
+            num(sorted.first().copied()),
+            num(avg),
+            num(sorted.last().copied()),
+            num(stddev),
+            num(pct(50.0)),
+            num(pct(95.0)),
+            num(pct(99.0)),
+            self.retried,
+            self.duplicates,
+            self.reordered,
+            self.unreachable,
+            self.warmed,
+            self.notes.iter().map(note_json).collect::<Vec<_>>().join(","),
+            self.started.elapsed().as_secs_f64(),
+        )
+    }
+
+    /// Writes the summary to `path`, or to stdout when it is `-`.
+    pub fn write(&self, path: &Path) -> Result<()> {
+        let json = self.to_json();
+        if path == Path::new("-") {
+            println!("{json}");
+            return Ok(());
+        }
+        std::fs::write(path, format!("{json}\n")).with_context(|| format!("failed to write `{}`", path.display()))
+    }
+}
+
+/// Nearest-rank percentile of already sorted values.
+fn nearest_rank(sorted: &[f64], p: f64) -> Option<f64> {
+    let n = sorted.len();
+    (n > 0).then(|| sorted[((p / 100.0 * n as f64).ceil() as usize).clamp(1, n) - 1])
+}
+
+fn json_str(s: &str) -> String {
+    let mut out = String::with_capacity(s.len() + 2);
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            c if c.is_control() => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+/// Quotes a CSV field when it holds the separator or a quote, doubling inner quotes.
+fn csv_field(s: &str, sep: char) -> String {
+    if s.contains(sep) || s.contains('"') {
+        format!("\"{}\"", s.replace('"', "\"\""))
+    } else {
+        s.to_string()
+    }
+}
+
+fn note_json(n: &Note) -> String {
+    format!("{{\"seq\":{},\"ts\":{},\"text\":{}}}", n.seq, TsFormat::EpochMs.format(n.ts), json_str(&n.text))
+}
+
+/// One sample as a JSON object, for `--serve`.
+pub fn sample_json(s: &PingSample) -> String {
+    let num = |v: Option<String>| v.unwrap_or_else(|| "null".into());
+    format!(
+        "{{\"seq\":{},\"ts\":{},\"rtt_ms\":{},\"ttl\":{},\"loaded\":{},\"flags\":{},\"error\":{}}}",
+        s.seq,
+        TsFormat::EpochMs.format(s.ts),
+        num(s.rtt_ms.map(|v| v.to_string())),
+        num(s.ttl.map(|v| v.to_string())),
+        s.loaded,
+        json_str(&s.flags.to_string()),
+        num(s.error.as_ref().map(|e| json_str(&e.to_string()))),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+
+    const BASE: u64 = 1_000_000; // seconds since the epoch, on a 10s bucket boundary
+
+    fn at(secs: u64) -> SystemTime {
+        UNIX_EPOCH + Duration::from_secs(BASE + secs)
+    }
+
+    fn sample(seq: u64, secs: u64) -> PingSample {
+        PingSample {
+            seq,
+            ts: at(secs),
+            sent: std::time::Instant::now(),
+            rtt_ms: Some(1.0),
+            error: None,
+            loaded: false,
+            retries: 0,
+            ttl: None,
+            flags: Flags::default(),
+            outcome: crate::pinger::Outcome::Reply,
+        }
+    }
+
+    /// Runs `steps` through an appender, each a time and whether it's a sample (or a
+    /// note typed then), and returns the rows after the header cut down to their
+    /// timestamp and note.
+    fn rows(name: &str, bucket: Option<u64>, steps: &[(u64, bool)]) -> Vec<String> {
+        let path = std::env::temp_dir().join(format!("rgping-csv-{name}-{}.csv", std::process::id()));
+        let summary = Arc::new(Mutex::new(Summary::new("h".into(), 0, false)));
+        let fmt = CsvFormat { sep: ',', ts: TsFormat::Epoch, bucket: bucket.map(Duration::from_secs) };
+        let mut csv = CsvAppender::open(&path, fmt, summary.clone()).unwrap();
+        let mut seq = 0;
+        for &(secs, is_sample) in steps {
+            if is_sample {
+                seq += 1;
+                csv.write(&sample(seq, secs)).unwrap();
+            } else {
+                summary.lock().unwrap().add_note(Note { seq, ts: at(secs), text: format!("n{secs}") });
+            }
+        }
+        drop(csv);
+        let text = fs::read_to_string(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        text.lines()
+            .skip(1)
+            .map(|l| {
+                let fields: Vec<&str> = l.split(',').collect();
+                let secs = fields[0].parse::<f64>().unwrap() as u64 - BASE;
+                format!("{secs} {}", fields.last().unwrap())
+            })
+            .collect()
+    }
+
+    #[test]
+    fn note_lands_after_its_bucket_row() {
+        let steps = [(1, true), (2, true), (3, false), (11, true), (15, false), (21, true), (35, false), (31, true)];
+        // Bucket rows carry the bucket's start and no note.
+        assert_eq!(rows("bucket", Some(10), &steps), ["0 ", "3 n3", "10 ", "15 n15", "20 ", "30 ", "35 n35"]);
+    }
+
+    #[test]
+    fn note_comes_before_the_next_sample() {
+        let steps = [(1, true), (2, false), (3, true), (4, false)];
+        assert_eq!(rows("samples", None, &steps), ["1 ", "2 n2", "3 ", "4 n4"]);
+    }
+}
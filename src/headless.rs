@@ -0,0 +1,138 @@
+use std::io::Write;
+use std::net::IpAddr;
+
+use anyhow::{bail, Result};
+use tokio::io::{AsyncBufReadExt, BufReader};
+use tokio::sync::mpsc::{self, Receiver};
+use tokio::sync::watch;
+
+use crate::dns;
+use crate::export::Anonymizer;
+use crate::pinger::{Outcome, PingConfig, PingSample, Pinger};
+use crate::ui::UiState;
+
+/// Keeps a single status line updated in place, without raw mode or the alternate screen.
+pub async fn run_oneline(host: String, history: usize, warmup: u64, mut rx: Receiver<PingSample>) -> Result<()> {
+    let mut state = UiState::new(history, history);
+    state.set_warmup(warmup);
+    let mut out = std::io::stdout();
+
+    while let Some(s) = rx.recv().await {
+        state.push(&s);
+        let last = state.last().map(|v| format!("{v:.1}ms")).unwrap_or_else(|| "timeout".into());
+        let avg = state.avg().map(|v| format!("{v:.1}")).unwrap_or_else(|| "-".into());
+        write!(out, "\r{host}  {last}  avg {avg}  loss {:.1}%\x1b[K", state.loss_pct())?;
+        out.flush()?;
+    }
+    Ok(())
+}
+
+/// Prints each RTT in milliseconds on its own line, or an empty line for a loss.
+pub async fn run_raw(mut rx: Receiver<PingSample>) -> Result<()> {
+    let mut out = std::io::stdout();
+    while let Some(s) = rx.recv().await {
+        match s.rtt_ms {
+            Some(ms) => writeln!(out, "{ms}")?,
+            None => writeln!(out)?,
+        }
+    }
+    Ok(())
+}
+
+/// Collects samples until the pinger stops, then prints `fmt` with the placeholders filled in.
+pub async fn run_status(host: String, fmt: String, warmup: u64, mut rx: Receiver<PingSample>) -> Result<()> {
+    let mut samples = Vec::new();
+    while let Some(s) = rx.recv().await {
+        samples.push(s);
+    }
+
+    let mut state = UiState::new(samples.len().max(1), samples.len().max(1));
+    state.set_warmup(warmup);
+    for s in &samples {
+        state.push(s);
+    }
+
+    let ms = |v: Option<f64>| v.map(|v| format!("{v:.0}")).unwrap_or_else(|| "-".into());
+    let line = fmt
+        .replace("{host}", &host)
+        .replace("{last}", &ms(state.last()))
+        .replace("{avg}", &ms(state.avg()))
+        .replace("{min}", &ms(state.min()))
+        .replace("{max}", &ms(state.max()))
+        .replace("{loss}", &format!("{:.0}", state.loss_pct()))
+        .replace("{sent}", &state.total().to_string());
+    println!("{line}");
+    Ok(())
+}
+
+/// Reads host names from stdin, one per line, and pings each for `base.count` probes in
+/// turn, printing a summary line per host. Blank lines and `#` comments are skipped.
+/// A host that doesn't resolve is reported and skipped; it's only an error if none do.
+pub async fn run_batch(base: PingConfig, anon: Anonymizer) -> Result<()> {
+    let mut lines = BufReader::new(tokio::io::stdin()).lines();
+    let (mut hosts, mut pinged) = (0, 0);
+    while let Some(line) = lines.next_line().await? {
+        let host = line.trim();
+        if host.is_empty() || host.starts_with('#') {
+            continue;
+        }
+        hosts += 1;
+        if host.parse::<IpAddr>().is_err() {
+            if let Err(e) = dns::resolve(host).await {
+                println!("{}  DNS error: {:#}", anon.host(host), e.root_cause());
+                continue;
+            }
+        }
+        pinged += 1;
+
+        let (tx, mut rx) = mpsc::channel(256);
+        let pinger = Pinger::new(PingConfig { host: host.to_string(), ..base.clone() });
+        tokio::spawn(pinger.run(tx));
+
+        let n = base.count.unwrap_or(1) as usize;
+        let mut state = UiState::new(n, n);
+        while let Some(s) = rx.recv().await {
+            state.push(&s);
+        }
+
+        let ms = |v: Option<f64>| v.map(|v| format!("{v:.1}")).unwrap_or_else(|| "-".into());
+        println!(
+            "{}  sent {}  loss {:.1}%  min/avg/max {}/{}/{} ms",
+            anon.host(host),
+            state.total(),
+            state.loss_pct(),
+            ms(state.min()),
+            ms(state.avg()),
+            ms(state.max()),
+        );
+    }
+    if hosts > 0 && pinged == 0 {
+        bail!("none of the hosts read from stdin could be resolved");
+    }
+    Ok(())
+}
+
+/// Sends one probe and prints its outcome; returns the sample, if the probe ran.
+pub async fn run_once(cfg: PingConfig, anon: Anonymizer) -> Result<Option<PingSample>> {
+    let literal = cfg.host.parse::<IpAddr>().ok();
+    let host = anon.host(&cfg.host);
+
+    // The address printed is the one the probe went to, from the pinger's own lookup.
+    let (tx, mut rx) = mpsc::channel(1);
+    let (lookups, resolved) = watch::channel(None);
+    tokio::spawn(Pinger::new(PingConfig { count: Some(1), ..cfg }).report_lookups(lookups).run(tx));
+    let sample = rx.recv().await;
+
+    let ip = literal.or(resolved.borrow().map(|r| r.addr));
+    let ip = ip.map(|ip| anon.ip(ip)).unwrap_or_else(|| "-".into());
+    let rtt = sample.as_ref().and_then(|s| s.rtt_ms);
+    match (rtt, sample.as_ref().map(|s| s.outcome)) {
+        (Some(ms), _) => println!("{host} {ip} {ms:.1}ms"),
+        (None, Some(Outcome::Unreachable(e))) => println!("{host} {ip} {}", anon.icmp(&e)),
+        (None, _) => println!("{host} {ip} timeout"),
+    }
+    if let Some(err) = sample.as_ref().and_then(|s| s.error.as_ref()) {
+        eprintln!("error: {err}");
+    }
+    Ok(sample)
+}
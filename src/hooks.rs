@@ -0,0 +1,115 @@
+use std::process::Stdio;
+
+use tokio::process::Command;
+
+use crate::pinger::PingSample;
+
+/// Runs the `--on-down`/`--on-up` commands when the host goes down or comes back.
+/// Both edges need several samples in a row, so a single lost or lucky probe on a
+/// flapping link doesn't fire anything.
+pub struct Hooks {
+    host: String,
+    on_down: Option<String>,
+    on_up: Option<String>,
+    down_after: u32,
+    up_after: u32,
+    down: bool,
+    // Consecutive samples that disagree with the current state.
+    streak: u32,
+}
+
+impl Hooks {
+    pub fn new(host: String, on_down: Option<String>, on_up: Option<String>, down_after: u32, up_after: u32) -> Self {
+        Self { host, on_down, on_up, down_after: down_after.max(1), up_after: up_after.max(1), down: false, streak: 0 }
+    }
+
+    pub fn observe(&mut self, s: &PingSample) {
+        let lost = s.rtt_ms.is_none();
+        if lost != self.down {
+            self.streak += 1;
+        } else {
+            self.streak = 0;
+        }
+        let needed = if self.down { self.up_after } else { self.down_after };
+        if self.streak < needed {
+            return;
+        }
+        self.down = lost;
+        self.streak = 0;
+        let (cmd, event) = if lost { (&self.on_down, "down") } else { (&self.on_up, "up") };
+        if let Some(cmd) = cmd {
+            self.spawn(cmd, event);
+        }
+    }
+
+    /// Starts `cmd` through the shell with the host and event as `$1`/`$2` and in
+    /// `RGPING_HOST`/`RGPING_EVENT`. Its output is discarded so it can't draw over the
+    /// UI, and it is reaped in the background.
+    fn spawn(&self, cmd: &str, event: &str) {
+        let child = Command::new("sh")
+            .args(["-c", cmd, "rgping", &self.host, event])
+            .env("RGPING_HOST", &self.host)
+            .env("RGPING_EVENT", event)
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn();
+        if let Ok(mut child) = child {
+            tokio::spawn(async move {
+                let _ = child.wait().await;
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Instant, SystemTime};
+
+    use super::*;
+    use crate::pinger::{Flags, Outcome};
+
+    fn sample(seq: u64, up: bool) -> PingSample {
+        PingSample {
+            seq,
+            ts: SystemTime::now(),
+            sent: Instant::now(),
+            rtt_ms: up.then_some(1.0),
+            error: None,
+            loaded: false,
+            retries: 0,
+            ttl: None,
+            flags: Flags::default(),
+            outcome: if up { Outcome::Reply } else { Outcome::Timeout },
+        }
+    }
+
+    /// Whether the host counts as down after each sample.
+    fn states(down_after: u32, up_after: u32, replies: &str) -> String {
+        let mut hooks = Hooks::new("h".into(), None, None, down_after, up_after);
+        replies
+            .chars()
+            .enumerate()
+            .map(|(i, c)| {
+                hooks.observe(&sample(i as u64 + 1, c == '+'));
+                if hooks.down { 'D' } else { 'U' }
+            })
+            .collect()
+    }
+
+    #[test]
+    fn down_needs_down_after_losses_in_a_row() {
+        // Two losses then a reply start over; the third loss in a row goes down.
+        assert_eq!(states(3, 1, "+--+---+"), "UUUUUUDU");
+    }
+
+    #[test]
+    fn up_needs_up_after_replies_in_a_row() {
+        assert_eq!(states(1, 2, "-+-++"), "DDDDU");
+    }
+
+    #[test]
+    fn zero_thresholds_act_as_one() {
+        assert_eq!(states(0, 0, "+-+-"), "UDUD");
+    }
+}
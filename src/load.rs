@@ -0,0 +1,61 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+use anyhow::{Context, Result};
+use tokio::{io::AsyncWriteExt, net::TcpStream, time::{sleep, sleep_until, timeout_at, Duration, Instant}};
+
+#[derive(Debug, Clone)]
+pub struct LoadConfig {
+    /// `host:port` of an endpoint that accepts and discards a TCP upload.
+    pub target: String,
+    /// Length of each idle and each loaded phase.
+    pub phase: Duration,
+}
+
+/// Alternates idle and loaded phases, saturating the uplink with a TCP upload
+/// during the loaded ones. `active` tells the pinger which phase a probe fell in.
+pub struct LoadGen {
+    cfg: LoadConfig,
+    active: Arc<AtomicBool>,
+}
+
+impl LoadGen {
+    pub fn new(cfg: LoadConfig) -> Self {
+        Self { cfg, active: Arc::new(AtomicBool::new(false)) }
+    }
+
+    pub fn active(&self) -> Arc<AtomicBool> {
+        self.active.clone()
+    }
+
+    /// Fails early if the endpoint can't be reached at all.
+    pub async fn check(&self) -> Result<()> {
+        TcpStream::connect(&self.cfg.target)
+            .await
+            .with_context(|| format!("failed to connect to load endpoint `{}`", self.cfg.target))?;
+        Ok(())
+    }
+
+    pub async fn run(self) -> Result<()> {
+        loop {
+            sleep(self.cfg.phase).await;
+
+            // A failed connect or write leaves the rest of the phase idle so that
+            // probes are never mislabelled as loaded.
+            let deadline = Instant::now() + self.cfg.phase;
+            let _ = timeout_at(deadline, self.saturate()).await;
+            self.active.store(false, Ordering::Relaxed);
+            sleep_until(deadline).await;
+        }
+    }
+
+    async fn saturate(&self) -> Result<()> {
+        let mut stream = TcpStream::connect(&self.cfg.target).await?;
+        self.active.store(true, Ordering::Relaxed);
+
+        let buf = vec![0u8; 64 * 1024];
+        loop {
+            stream.write_all(&buf).await?;
+        }
+    }
+}
@@ -1,63 +1,731 @@
+mod color;
+mod diag;
+mod dns;
+mod export;
+mod headless;
+mod hooks;
+mod load;
 mod pinger;
+mod prefs;
+mod serve;
 mod ui;
 
+use std::net::{IpAddr, Ipv6Addr};
+use std::path::PathBuf;
+use std::process::ExitCode;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, OnceLock};
 use std::time::Duration;
 
-use anyhow::Result;
-use clap::{Parser, ValueHint};
+use anyhow::{bail, Result};
+use clap::{parser::ValueSource, CommandFactory, FromArgMatches, Parser, ValueHint};
 use tokio::signal;
-use tokio::sync::mpsc;
+use tokio::sync::{broadcast, watch};
+use tokio::sync::mpsc::{self, error::TrySendError};
 
-use pinger::{Pinger, PingConfig};
-use ui::{Ui, UiConfig};
+use color::{ColorDepth, Palette};
+use diag::ChannelStats;
+use export::{Anonymizer, CsvAppender, CsvFormat, Summary, TsFormat};
+use hooks::Hooks;
+use load::{LoadConfig, LoadGen};
+use pinger::{Pinger, PingConfig, Resolve};
+use ui::{Central, MarkerKind, Ui, UiConfig, UnreachableAs};
 
 #[derive(Parser, Debug)]
 #[command(name = "rgping", version, about = "Minimal gping-like live latency graph in your terminal")]
 struct Args {
-    #[arg(value_hint = ValueHint::Hostname)]
-    host: String,
+    /// Host to ping, or `-` to read hosts from stdin and print a summary for each (no TUI)
+    #[arg(value_hint = ValueHint::Hostname, value_parser = parse_host, required_unless_present = "diagnostics")]
+    host: Option<String>,
 
+    /// Time between probes; raised to --min-interval-ms if lower (ignored with --flood)
     #[arg(short = 'i', long, default_value_t = 1000)]
     interval_ms: u64,
 
+    /// Smallest --interval-ms accepted, so a typo like `-i 0` doesn't flood the target
+    #[arg(long, value_name = "MS", default_value_t = 50)]
+    min_interval_ms: u64,
+
+    /// Move each probe by a random amount within ±PCT% of the interval, so probes don't
+    /// line up with other periodic traffic
+    #[arg(long, value_name = "PCT", default_value_t = 0.0)]
+    jitter: f64,
+
+    /// The `ping` program to run for each probe, e.g. a specific build or a wrapper script
+    #[arg(long, value_name = "PATH", default_value = "ping", value_hint = ValueHint::CommandName)]
+    ping_bin: String,
+
+    /// When a host name is looked up: once at startup, before every probe, or
+    /// periodically (see `--reresolve-every`)
+    #[arg(long, value_enum, default_value_t = Resolve::PerProbe)]
+    resolve: Resolve,
+
+    /// With `--resolve periodic`, seconds between lookups
+    #[arg(long, value_name = "SECS", default_value_t = 300, value_parser = clap::value_parser!(u64).range(1..))]
+    reresolve_every: u64,
+
     #[arg(short = 't', long, default_value_t = 1000)]
     timeout_ms: u64,
 
     #[arg(short = 'H', long, default_value_t = 120)]
     history: usize,
+
+    /// Number of samples kept for scrolling back (at least `history`, at most 10 million)
+    #[arg(short = 'b', long, default_value_t = 3600, value_parser = clap::value_parser!(u64).range(1..=ui::MAX_BACKLOG as u64))]
+    backlog: u64,
+
+    /// Samples kept one by one; older ones in the backlog are averaged 10 to a point.
+    /// Scrolling far back then shows coarser detail, but a multi-day backlog takes
+    /// about a tenth of the memory
+    #[arg(long, value_name = "N", default_value_t = 100_000)]
+    max_samples: usize,
+
+    /// Append each sample to this CSV file as it arrives; notes added in the TUI get
+    /// a row of their own with the text in the last column
+    #[arg(long, value_name = "PATH", value_hint = ValueHint::FilePath)]
+    csv_append: Option<PathBuf>,
+
+    /// CSV field separator (a single character, or `tab`)
+    #[arg(long, value_name = "CHAR", default_value = ",", value_parser = export::parse_sep)]
+    csv_sep: char,
+
+    /// Aggregate `--csv-append` output into buckets of this many seconds: one
+    /// min/avg/max/loss row per bucket instead of one row per sample
+    #[arg(long, value_name = "SECS", requires = "csv_append", value_parser = clap::value_parser!(u64).range(1..))]
+    export_bucket: Option<u64>,
+
+    /// On exit, write a JSON summary of the whole run to this file (`-` for stdout)
+    #[arg(long, value_name = "PATH", value_hint = ValueHint::FilePath)]
+    summary_json: Option<PathBuf>,
+
+    /// Timestamp format used in CSV output
+    #[arg(long, value_enum, default_value_t = TsFormat::Rfc3339)]
+    ts_format: TsFormat,
+
+    /// Alternate idle and loaded phases, loading the link with a TCP upload to this `host:port`
+    #[arg(long, value_name = "HOST:PORT")]
+    load: Option<String>,
+
+    /// Length of each idle/loaded phase when `--load` is set
+    #[arg(long, default_value = "10s", value_parser = humantime::parse_duration)]
+    load_phase: Duration,
+
+    /// Statistic shown in the footer's central slot (cycle it with `c`)
+    #[arg(long, visible_alias = "footer-stat", value_enum, default_value_t = Central::Arith)]
+    central: Central,
+
+    /// Smooth the chart with a median over this many neighbouring samples (display only)
+    #[arg(long, value_name = "K", default_value_t = 1)]
+    median_filter: usize,
+
+    /// Show the footer's "last" value as the mean of the newest N samples; 1 shows the raw
+    /// last sample (exports always get raw samples)
+    #[arg(long, value_name = "N", default_value_t = 1)]
+    last_avg: usize,
+
+    /// Leave the first N samples (ARP, route and DNS setup) out of all statistics; they
+    /// are still plotted and exported
+    #[arg(long, value_name = "N", default_value_t = 0)]
+    warmup: u64,
+
+    /// Count ICMP "unreachable" replies as loss, or show them separately and leave them
+    /// out of the loss figure
+    #[arg(long, value_enum, default_value_t = UnreachableAs::Loss)]
+    unreachable_as: UnreachableAs,
+
+    /// Retry a timed-out probe up to N times while the interval still has time left
+    #[arg(long, value_name = "N", default_value_t = 0)]
+    retries: u32,
+
+    /// Print a single status line updated in place instead of the full TUI
+    #[arg(long)]
+    oneline: bool,
+
+    /// Stop after sending N probes (defaults to 3 with `--status` and for hosts read from stdin)
+    #[arg(short = 'c', long, value_name = "N")]
+    count: Option<u64>,
+
+    /// Send a single probe, print `<host> <ip> <rtt|timeout|ICMP error>` and exit 0 on a reply, 1 otherwise
+    #[arg(long, conflicts_with_all = ["status", "oneline"])]
+    once: bool,
+
+    /// Run the TUI as a pass/fail check: on quit, exit 1 if `--fail-loss` or `--fail-p95` was exceeded
+    #[arg(long, conflicts_with_all = ["status", "oneline", "once", "raw"])]
+    watch: bool,
+
+    /// With `--watch`, loss (percent) above which the session counts as failed
+    #[arg(long, value_name = "PCT", requires = "watch")]
+    fail_loss: Option<f64>,
+
+    /// With `--watch`, 95th-percentile RTT (ms) above which the session counts as failed
+    #[arg(long, value_name = "MS", requires = "watch")]
+    fail_p95: Option<f64>,
+
+    /// Print only each RTT in ms, one per line (an empty line for a loss), for piping
+    #[arg(long, conflicts_with_all = ["status", "oneline", "once"])]
+    raw: bool,
+
+    /// Take a few samples, print one summary line and exit
+    #[arg(long)]
+    status: bool,
+
+    /// Summary line for `--status`; placeholders: {host} {last} {avg} {min} {max} {loss} {sent}
+    #[arg(long, value_name = "FMT", default_value = "{avg}ms {loss}%")]
+    status_format: String,
+
+    /// Number of evenly spaced labels on the chart's RTT axis, including 0 and the top
+    #[arg(long, value_name = "N", default_value_t = 3, value_parser = clap::value_parser!(u16).range(2..))]
+    y_ticks: u16,
+
+    /// Space left above the highest RTT on the chart, in percent; 0 lets the line touch the top
+    #[arg(long, value_name = "PCT", default_value_t = 20.0)]
+    y_headroom: f64,
+
+    /// Glyphs used to draw the chart lines
+    #[arg(long, value_enum, default_value_t = MarkerKind::Dot)]
+    marker: MarkerKind,
+
+    /// RTT (ms) at or above which samples are shown as degraded
+    #[arg(long, value_name = "MS", default_value_t = 100.0)]
+    warn: f64,
+
+    /// RTT (ms) at or above which samples are shown as critical
+    #[arg(long, value_name = "MS", default_value_t = 250.0)]
+    crit: f64,
+
+    /// Loss (%) above which the footer shows loss as degraded
+    #[arg(long, value_name = "PCT", default_value_t = 0.0)]
+    loss_warn: f64,
+
+    /// Loss (%) above which the footer shows loss as critical
+    #[arg(long, value_name = "PCT", default_value_t = 5.0)]
+    loss_crit: f64,
+
+    /// Start probes on a fixed schedule even if earlier ones haven't finished, allowing up
+    /// to N in flight (`--fixed-rate=N`, 4 if not given); keeps the sample rate constant, but
+    /// samples are shown in completion order
+    #[arg(long, value_name = "N", num_args = 0..=1, require_equals = true, default_missing_value = "4")]
+    fixed_rate: Option<usize>,
+
+    /// Print OS, ping binary, ICMP permission, resolver and terminal details, then exit
+    #[arg(long)]
+    diagnostics: bool,
+
+    /// Colors the terminal supports; `auto` checks COLORTERM and TERM
+    #[arg(long, value_enum, default_value_t = ColorDepth::Auto)]
+    color_depth: ColorDepth,
+
+    /// Colors for RTT and loss severity; `colorblind` also marks each level with a shape
+    #[arg(long, value_enum, default_value_t = Palette::Default)]
+    palette: Palette,
+
+    /// Ping as fast as replies come back (like `ping -f`), capped by --max-rate; this
+    /// generates a lot of traffic, so only use it on links you're allowed to stress
+    #[arg(long)]
+    flood: bool,
+
+    /// Look up the target address's PTR name and show it in the header
+    #[arg(long)]
+    rdns: bool,
+
+    /// Show how long until the next probe, handy with slow intervals
+    #[arg(long)]
+    countdown: bool,
+
+    /// How often the TUI redraws when no samples arrive (clocks, countdown, indicators),
+    /// up to 30; defaults to 10 with `--countdown` and 1 otherwise
+    #[arg(long, value_name = "FPS")]
+    ui_fps: Option<f64>,
+
+    /// Title of the latency chart
+    #[arg(long, value_name = "TEXT", default_value = "Latency")]
+    title: String,
+
+    /// Name shown in the header instead of the host, e.g. the service an IP stands for
+    #[arg(long, value_name = "TEXT")]
+    label: Option<String>,
+
+    /// Draw in the normal screen instead of the alternate one, leaving the last frame in
+    /// the scrollback on exit
+    #[arg(long)]
+    no_altscreen: bool,
+
+    /// Stream samples as Server-Sent Events from a small HTTP server on this address
+    #[arg(long, value_name = "ADDR:PORT")]
+    serve: Option<String>,
+
+    /// Shell command to run when the host goes down; gets the host and `down` as $1/$2
+    /// and in RGPING_HOST/RGPING_EVENT
+    #[arg(long, value_name = "CMD")]
+    on_down: Option<String>,
+
+    /// Shell command to run when the host comes back up; gets the host and `up` as $1/$2
+    /// and in RGPING_HOST/RGPING_EVENT
+    #[arg(long, value_name = "CMD")]
+    on_up: Option<String>,
+
+    /// Consecutive losses before the host counts as down
+    #[arg(long, value_name = "N", default_value_t = 3, value_parser = clap::value_parser!(u32).range(1..))]
+    down_after: u32,
+
+    /// Consecutive replies before a down host counts as up again
+    #[arg(long, value_name = "N", default_value_t = 3, value_parser = clap::value_parser!(u32).range(1..))]
+    up_after: u32,
+
+    /// Replace IP addresses (the target's, the source's and routers' in ICMP errors) with
+    /// pseudonyms in all output, stable for the run, so logs can be shared
+    #[arg(long)]
+    anonymize: bool,
+
+    /// Capacity of the sample queues; when the TUI falls this far behind, samples skip it
+    /// (they are still written to CSV and the summary). Headless output never skips samples
+    #[arg(long, value_name = "N", default_value_t = 256, value_parser = clap::value_parser!(u64).range(1..))]
+    channel_size: u64,
+
+    /// Upper bound on probes per second in --flood mode
+    #[arg(long, value_name = "PPS", default_value_t = 100.0, requires = "flood")]
+    max_rate: f64,
+}
+
+/// Accepts a host name or IP literal; ICMP has no ports, so `host:port` and URLs are rejected.
+fn parse_host(s: &str) -> Result<String, String> {
+    if s.parse::<IpAddr>().is_ok() {
+        return Ok(s.to_string());
+    }
+    if let Some((scheme, rest)) = s.split_once("://") {
+        let host = rest.split(['/', ':']).next().unwrap_or_default();
+        return Err(format!("`{scheme}://` URLs aren't supported; ICMP pings a bare host, try `{host}`"));
+    }
+    if let Some(inner) = s.strip_prefix('[') {
+        return match inner.split_once(']') {
+            Some((ip, "")) if ip.parse::<Ipv6Addr>().is_ok() => Ok(ip.to_string()),
+            Some((ip, _)) => Err(format!("ICMP has no ports; pass just the address, e.g. `{ip}`")),
+            None => Err(format!("unterminated `[` in `{s}`")),
+        };
+    }
+    if let Some((host, port)) = s.split_once(':') {
+        if !port.contains(':') {
+            let what = if port.parse::<u16>().is_ok() { "a port" } else { "a `:` suffix" };
+            return Err(format!("ICMP has no ports, but `{s}` has {what}; try `{host}`"));
+        }
+    }
+    Ok(s.to_string())
+}
+
+/// Ctrl-C, or SIGTERM on Unix (e.g. `systemctl stop` or a plain `kill`).
+async fn shutdown_signal() {
+    #[cfg(unix)]
+    let terminate = async {
+        match signal::unix::signal(signal::unix::SignalKind::terminate()) {
+            Ok(mut sig) => {
+                sig.recv().await;
+            }
+            Err(_) => std::future::pending().await,
+        }
+    };
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        _ = signal::ctrl_c() => {}
+        _ = terminate => {}
+    }
 }
 
 #[tokio::main]
-async fn main() -> Result<()> {
-    let args = Args::parse();
+async fn main() -> Result<ExitCode> {
+    let matches = Args::command().get_matches();
+    let args = Args::from_arg_matches(&matches)?;
+
+    if args.diagnostics {
+        let resolve = (args.resolve, Duration::from_secs(args.reresolve_every));
+        diag::run(args.host.as_deref(), &args.ping_bin, resolve, args.channel_size as usize).await?;
+        return Ok(ExitCode::SUCCESS);
+    }
+    let host = args.host.clone().expect("clap requires a host unless --diagnostics is set");
+
+    // Saved view settings apply unless the matching flag was given explicitly.
+    let mut prefs = prefs::load();
+    if matches.value_source("history") == Some(ValueSource::CommandLine) {
+        prefs.window = None;
+    }
+    if matches.value_source("central") == Some(ValueSource::CommandLine) {
+        prefs.central = None;
+    }
+
+    let mut warnings = Vec::new();
+    let interval_ms = if !args.flood && args.interval_ms < args.min_interval_ms {
+        warnings.push(format!(
+            "--interval-ms {} is below the {}ms floor, using {}ms (lower --min-interval-ms, or use --flood)",
+            args.interval_ms, args.min_interval_ms, args.min_interval_ms,
+        ));
+        args.min_interval_ms
+    } else {
+        args.interval_ms
+    };
+    // A zero interval would spin (and panics tokio's fixed-rate ticker), whatever
+    // `-i 0 --min-interval-ms 0` or a huge `--max-rate` asked for.
+    let interval = if args.flood {
+        Duration::from_secs_f64(1.0 / args.max_rate.max(0.1))
+    } else {
+        Duration::from_millis(interval_ms)
+    }.max(Duration::from_millis(1));
+    let jitter = args.jitter.clamp(0.0, 100.0) / 100.0;
+
+    if args.flood {
+        warnings.push(format!(
+            "--flood sends up to {} probes per second; stop if the network or its owner objects",
+            args.max_rate,
+        ));
+    } else if args.timeout_ms > interval_ms && args.fixed_rate.is_none() {
+        warnings.push(format!(
+            "--timeout-ms ({}) is longer than --interval-ms ({}): probes run one at a time, \
+             so each lost probe holds up the next and the effective interval stretches toward the timeout \
+             (see --fixed-rate)",
+            args.timeout_ms, interval_ms,
+        ));
+    }
+    for w in &warnings {
+        eprintln!("warning: {w}");
+    }
+
+    let anon = Anonymizer::new(args.anonymize);
 
-    let (tx, rx) = mpsc::channel::<pinger::PingSample>(256);
+    if args.once {
+        let sample = headless::run_once(PingConfig {
+            host: host.clone(),
+            interval,
+            timeout: Duration::from_millis(args.timeout_ms),
+            load: None,
+            retries: args.retries,
+            count: Some(1),
+            max_inflight: None,
+            jitter: 0.0,
+            ping_bin: args.ping_bin.clone(),
+            pause: None,
+            resolve: args.resolve,
+            reresolve_every: Duration::from_secs(args.reresolve_every),
+        }, anon.clone()).await?;
+        if let Some(path) = &args.summary_json {
+            let separate = args.unreachable_as == UnreachableAs::Separate;
+            let mut summary = Summary::new(anon.host(&host), args.warmup, separate);
+            if let Some(s) = &sample {
+                summary.push(s);
+            }
+            summary.write(path)?;
+        }
+        let replied = sample.is_some_and(|s| s.rtt_ms.is_some());
+        return Ok(if replied { ExitCode::SUCCESS } else { ExitCode::FAILURE });
+    }
+
+    if host == "-" {
+        if args.summary_json.is_some() {
+            bail!("--summary-json covers a single host; it can't be used with hosts read from stdin");
+        }
+        return headless::run_batch(PingConfig {
+            host,
+            interval,
+            timeout: Duration::from_millis(args.timeout_ms),
+            load: None,
+            retries: args.retries,
+            count: Some(args.count.unwrap_or(3)),
+            max_inflight: args.fixed_rate.map(|n| n.max(1)),
+            jitter,
+            ping_bin: args.ping_bin.clone(),
+            pause: None,
+            resolve: args.resolve,
+            reresolve_every: Duration::from_secs(args.reresolve_every),
+        }, anon).await.map(|()| ExitCode::SUCCESS);
+    }
+
+    let channel_size = args.channel_size as usize;
+    let (tx, mut samples) = mpsc::channel::<pinger::PingSample>(channel_size);
+    let (ui_tx, rx) = mpsc::channel::<pinger::PingSample>(channel_size);
+    let channel = Arc::new(ChannelStats::new(channel_size));
+
+    let csv_fmt = CsvFormat {
+        sep: args.csv_sep,
+        ts: args.ts_format,
+        bucket: args.export_bucket.map(Duration::from_secs),
+    };
+    let summary = Arc::new(Mutex::new(Summary::new(
+        anon.host(&host),
+        args.warmup,
+        args.unreachable_as == UnreachableAs::Separate,
+    )));
+    let mut csv = args
+        .csv_append
+        .as_deref()
+        .map(|p| CsvAppender::open(p, csv_fmt, summary.clone()))
+        .transpose()?;
 
+    let load_gen = args.load.clone().map(|target| LoadGen::new(LoadConfig {
+        target,
+        phase: args.load_phase,
+    }));
+    if let Some(load_gen) = &load_gen {
+        load_gen.check().await?;
+    }
+
+    // The pinger starts from this lookup and publishes its own later ones, so the
+    // header always names the address being probed. An IP literal needs none.
+    let literal = host.parse::<IpAddr>().ok();
+    let resolved = match literal {
+        Some(_) => None,
+        None => dns::resolve(&host).await.ok(),
+    };
+    let addr = literal.or(resolved.map(|r| r.addr));
+    let source = match addr {
+        Some(addr) => dns::source_for(addr).await.ok(),
+        None => None,
+    };
+    // Filled in whenever the reverse lookup answers; the header shows it from then on.
+    let ptr = Arc::new(OnceLock::new());
+    if let (true, Some(addr)) = (args.rdns, addr) {
+        let ptr = ptr.clone();
+        tokio::spawn(async move {
+            if let Ok(name) = dns::reverse(addr).await {
+                let _ = ptr.set(name);
+            }
+        });
+    }
+
+    // The TUI can stop and restart probing; headless modes never pause.
+    let (pause, pause_rx) = watch::channel(false);
     let pinger_cfg = PingConfig {
-        host: args.host.clone(),
-        interval: Duration::from_millis(args.interval_ms),
+        host: host.clone(),
+        interval,
         timeout: Duration::from_millis(args.timeout_ms),
+        load: load_gen.as_ref().map(LoadGen::active),
+        retries: args.retries,
+        count: args.count.or(args.status.then_some(3)),
+        max_inflight: args.fixed_rate.map(|n| n.max(1)),
+        jitter,
+        ping_bin: args.ping_bin.clone(),
+        pause: Some(pause_rx),
+        resolve: args.resolve,
+        reresolve_every: Duration::from_secs(args.reresolve_every),
     };
-    let pinger = Pinger::new(pinger_cfg);
+    let (lookups, resolved) = watch::channel(resolved);
+    let pinger = Pinger::new(pinger_cfg).report_lookups(lookups);
 
-    let ping_task = tokio::spawn(async move {
+    tokio::spawn(async move {
         let _ = pinger.run(tx).await;
     });
 
-    let ui = Ui::new(UiConfig {
-        host: args.host.clone(),
-        history: args.history,
+    if let Some(load_gen) = load_gen {
+        tokio::spawn(load_gen.run());
+    }
+
+    let relay_summary = summary.clone();
+    let relay_channel = channel.clone();
+    let mut server = None;
+    let events = match &args.serve {
+        Some(addr) => {
+            let (events, _) = broadcast::channel(channel_size);
+            let listener = serve::bind(addr).await?;
+            let task = tokio::spawn(serve::run(listener, events.clone(), summary.clone()));
+            server = Some(task);
+            Some(events)
+        }
+        None => None,
+    };
+    let mut hooks = (args.on_down.is_some() || args.on_up.is_some())
+        .then(|| Hooks::new(host.clone(), args.on_down.clone(), args.on_up.clone(), args.down_after, args.up_after));
+    let tui = !args.status && !args.oneline && !args.raw;
+    let mut relay_task = tokio::spawn(async move {
+        while let Some(s) = samples.recv().await {
+            relay_summary.lock().unwrap().push(&s);
+            if let Some(hooks) = hooks.as_mut() {
+                hooks.observe(&s);
+            }
+            if let Some(events) = &events {
+                // No subscribers is fine; there may be no clients connected.
+                let _ = events.send(s.clone());
+            }
+            if let Some(csv) = csv.as_mut() {
+                csv.write(&s)?;
+            }
+            // Don't let a slow TUI hold up the pinger; CSV and the summary still see every
+            // sample. Headless output is read by other programs and must not skip lines,
+            // so there a slow reader slows the relay down instead.
+            if tui {
+                match ui_tx.try_send(s) {
+                    Ok(()) => relay_channel.record(channel_size - ui_tx.capacity()),
+                    Err(TrySendError::Full(_)) => relay_channel.record_drop(),
+                    Err(TrySendError::Closed(_)) => break,
+                }
+            } else {
+                if ui_tx.send(s).await.is_err() {
+                    break;
+                }
+                relay_channel.record(channel_size - ui_tx.capacity());
+            }
+        }
+        if let Some(csv) = csv.as_mut() {
+            csv.flush()?;
+        }
+        anyhow::Ok(())
     });
 
-    let ui_task = tokio::task::spawn_blocking(move || ui.run_tui(rx));
+    // Asks the TUI to restore the terminal and return when we're told to stop.
+    let stop = Arc::new(AtomicBool::new(false));
+    let mut ui_task = if args.status {
+        tokio::spawn(headless::run_status(anon.host(&host), args.status_format.clone(), args.warmup, rx))
+    } else if args.raw {
+        tokio::spawn(headless::run_raw(rx))
+    } else if args.oneline {
+        tokio::spawn(headless::run_oneline(anon.host(&host), args.history, args.warmup, rx))
+    } else {
+        let ui = Ui::new(UiConfig {
+            host: host.clone(),
+            label: args.label.clone(),
+            title: args.title.clone(),
+            history: args.history,
+            backlog: args.backlog as usize,
+            max_samples: args.max_samples,
+            load: args.load.is_some(),
+            central: args.central,
+            median_filter: args.median_filter,
+            y_ticks: args.y_ticks as usize,
+            y_headroom: args.y_headroom.max(0.0),
+            last_avg: args.last_avg,
+            warmup: args.warmup,
+            retries: args.retries > 0,
+            unreachable_as: args.unreachable_as,
+            marker: args.marker.into(),
+            warn_ms: args.warn,
+            crit_ms: args.crit,
+            loss_warn: args.loss_warn,
+            loss_crit: args.loss_crit,
+            resolved,
+            literal: literal.is_some(),
+            ptr,
+            source,
+            prefs,
+            warnings,
+            color_depth: args.color_depth.detect(),
+            palette: args.palette,
+            time_axis: args.flood,
+            channel: channel.clone(),
+            interval,
+            countdown: args.countdown,
+            ui_fps: args.ui_fps.map(|fps| fps.clamp(0.1, 30.0)),
+            altscreen: !args.no_altscreen,
+            stop: stop.clone(),
+            pause,
+            summary: summary.clone(),
+            anon,
+        });
+        tokio::spawn(ui.run_tui(rx))
+    };
 
-    tokio::select! {
-        _ = signal::ctrl_c() => {},
-        _ = ping_task => {},
-        ui_res = ui_task => {
-            ui_res??;
+    // The relay finishes on its own once a `--count` run is done; keep waiting on
+    // the UI (or headless output) after that so it can show the final samples.
+    let mut relay_done = false;
+    let mut ui_done = false;
+    let shutdown = shutdown_signal();
+    tokio::pin!(shutdown);
+    loop {
+        tokio::select! {
+            _ = &mut shutdown => break,
+            relay_res = &mut relay_task, if !relay_done => {
+                relay_res??;
+                relay_done = true;
+            }
+            ui_res = &mut ui_task => {
+                ui_done = true;
+                ui_res??;
+                break;
+            }
+        }
+    }
+    if tui && !ui_done {
+        stop.store(true, Ordering::Relaxed);
+        ui_task.await??;
+    }
+    // Stopping the relay drops the sender, which ends every client stream. Wait for it
+    // to go so the CSV appender is dropped, flushing buffered rows and the last bucket.
+    relay_task.abort();
+    if !relay_done {
+        match relay_task.await {
+            Ok(res) => res?,
+            Err(e) if e.is_cancelled() => {}
+            Err(e) => return Err(e.into()),
+        }
+    }
+    if let Some(server) = server {
+        server.abort();
+    }
+
+    if args.oneline {
+        println!();
+    }
+    if channel.dropped() > 0 {
+        eprintln!(
+            "warning: the display fell behind and skipped {} samples (queue high-water {}/{}); \
+             a larger --channel-size may help",
+            channel.dropped(), channel.high_water(), channel.capacity,
+        );
+    }
+    if let Some(path) = &args.summary_json {
+        summary.lock().unwrap().write(path)?;
+    }
+    if args.watch {
+        let failures = watch_failures(&summary.lock().unwrap(), args.fail_loss, args.fail_p95);
+        for f in &failures {
+            eprintln!("rgping: {f}");
+        }
+        if !failures.is_empty() {
+            return Ok(ExitCode::FAILURE);
+        }
+    }
+
+    Ok(ExitCode::SUCCESS)
+}
+
+/// Which `--watch` thresholds the whole run went over; thresholds not given aren't checked.
+fn watch_failures(summary: &Summary, fail_loss: Option<f64>, fail_p95: Option<f64>) -> Vec<String> {
+    let mut failures = Vec::new();
+    if let Some(max) = fail_loss {
+        let loss = summary.loss();
+        if loss > max {
+            failures.push(format!("loss {loss:.1}% is above --fail-loss {max}%"));
+        }
+    }
+    if let Some(max) = fail_p95 {
+        match summary.p95() {
+            Some(p95) if p95 > max => failures.push(format!("p95 {p95:.1} ms is above --fail-p95 {max} ms")),
+            Some(_) => {}
+            None => failures.push(format!("no replies, so p95 can't be within --fail-p95 {max} ms")),
         }
     }
+    failures
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hosts_and_ip_literals_pass() {
+        for host in ["example.com", "10.0.0.1", "::1", "fe80::1%eth0", "2001:db8::1"] {
+            assert_eq!(parse_host(host).as_deref(), Ok(host), "{host}");
+        }
+    }
+
+    #[test]
+    fn bracketed_v6_is_unwrapped() {
+        assert_eq!(parse_host("[2001:db8::1]").as_deref(), Ok("2001:db8::1"));
+        assert!(parse_host("[2001:db8::1]:443").unwrap_err().contains("e.g. `2001:db8::1`"));
+        assert!(parse_host("[2001:db8::1").unwrap_err().contains("unterminated"));
+    }
 
-    Ok(())
+    #[test]
+    fn ports_and_urls_are_rejected_with_the_host() {
+        assert!(parse_host("example.com:443").unwrap_err().contains("has a port; try `example.com`"));
+        assert!(parse_host("example.com:x").unwrap_err().contains("a `:` suffix"));
+        let url = parse_host("https://example.com:8443/path").unwrap_err();
+        assert!(url.contains("`https://` URLs") && url.contains("try `example.com`"), "{url}");
+    }
 }
@@ -1,13 +1,195 @@
-use anyhow::{Context, Result};
-#[cfg(not(target_os = "linux"))]
-use anyhow::anyhow;
+use anyhow::{anyhow, Context, Result};
+use clap::ValueEnum;
+use std::fmt;
+use std::net::IpAddr;
 use std::process::Stdio;
-use tokio::{process::Command, time::{sleep, Duration}};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::SystemTime;
+use tokio::{
+    process::Command,
+    sync::{mpsc, watch, Semaphore},
+    time::{interval, sleep, Duration, Instant, MissedTickBehavior},
+};
+
+use crate::dns::{self, Resolved};
+
+/// When a host name gets turned into the address probes go to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Resolve {
+    /// Look it up at startup and probe that address for the whole run
+    Once,
+    /// Look it up again before every probe
+    PerProbe,
+    /// Look it up at startup and again every `--reresolve-every`
+    Periodic,
+}
 
 #[derive(Debug, Clone)]
 pub struct PingSample {
     pub seq: u64,
+    pub ts: SystemTime, // wall-clock send time, for display and export
+    pub sent: std::time::Instant, // monotonic send time, for intervals and the time axis
     pub rtt_ms: Option<f64>, // None means timeout/loss
+    pub error: Option<ProbeError>, // set when the probe itself failed to run
+    pub loaded: bool, // sent while the load generator was saturating the link
+    pub retries: u32, // extra attempts it took before the probe got a reply
+    pub ttl: Option<u8>, // TTL of the reply, when ping reported one
+    pub flags: Flags,
+    pub outcome: Outcome,
+}
+
+/// How a probe ended. `Unreachable` means a router answered with an ICMP error, so
+/// unlike a timeout the network did respond.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Outcome {
+    Reply,
+    Timeout,
+    Unreachable(IcmpError),
+}
+
+/// An ICMP error that came back instead of an echo reply, and the router that sent it
+/// when ping named one.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct IcmpError {
+    pub reason: IcmpReason,
+    pub from: Option<IpAddr>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IcmpReason {
+    HostUnreachable,
+    NetUnreachable,
+    PortUnreachable,
+    ProtocolUnreachable,
+    Prohibited,
+    TtlExceeded,
+    FragNeeded,
+    Other,
+}
+
+impl IcmpReason {
+    /// Recognises the wording iputils and the BSDs use, for IPv4 and IPv6 alike.
+    fn parse(line: &str) -> Option<Self> {
+        let l = line.to_ascii_lowercase();
+        let reason = if l.contains("time to live exceeded") || l.contains("time exceeded") {
+            IcmpReason::TtlExceeded
+        } else if l.contains("frag needed") {
+            IcmpReason::FragNeeded
+        } else if l.contains("prohibited") || l.contains("packet filtered") {
+            IcmpReason::Prohibited
+        } else if l.contains("port unreachable") {
+            IcmpReason::PortUnreachable
+        } else if l.contains("protocol unreachable") {
+            IcmpReason::ProtocolUnreachable
+        } else if l.contains("net unreachable") || l.contains("no route") {
+            IcmpReason::NetUnreachable
+        } else if l.contains("host unreachable") || l.contains("address unreachable") {
+            IcmpReason::HostUnreachable
+        } else if l.contains("unreachable") {
+            IcmpReason::Other
+        } else {
+            return None;
+        };
+        Some(reason)
+    }
+}
+
+impl fmt::Display for IcmpReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            IcmpReason::HostUnreachable => "host unreachable",
+            IcmpReason::NetUnreachable => "net unreachable",
+            IcmpReason::PortUnreachable => "port unreachable",
+            IcmpReason::ProtocolUnreachable => "protocol unreachable",
+            IcmpReason::Prohibited => "admin prohibited",
+            IcmpReason::TtlExceeded => "TTL exceeded",
+            IcmpReason::FragNeeded => "fragmentation needed",
+            IcmpReason::Other => "unreachable",
+        })
+    }
+}
+
+impl fmt::Display for IcmpError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.reason)?;
+        if let Some(from) = self.from {
+            write!(f, " from {from}")?;
+        }
+        Ok(())
+    }
+}
+
+/// Things about a sample that its RTT alone doesn't show.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Flags(u8);
+
+impl Flags {
+    pub const RETRIED: Flags = Flags(1); // got a reply only after retrying
+    pub const DUPLICATE: Flags = Flags(2); // ping saw more than one reply to the probe
+    pub const REORDERED: Flags = Flags(4); // completed after a later probe did
+
+    const NAMES: [(Flags, &'static str); 3] =
+        [(Flags::RETRIED, "retried"), (Flags::DUPLICATE, "dup"), (Flags::REORDERED, "reordered")];
+
+    pub fn contains(self, f: Flags) -> bool {
+        self.0 & f.0 == f.0
+    }
+
+    pub fn insert(&mut self, f: Flags) {
+        self.0 |= f.0;
+    }
+
+    pub fn is_empty(self) -> bool {
+        self.0 == 0
+    }
+}
+
+impl fmt::Display for Flags {
+    /// Set flags separated by `|`, e.g. `retried|dup`; empty when none are set.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut sep = "";
+        for (flag, name) in Flags::NAMES {
+            if self.contains(flag) {
+                write!(f, "{sep}{name}")?;
+                sep = "|";
+            }
+        }
+        Ok(())
+    }
+}
+
+/// What a successful probe reports.
+#[derive(Debug, Clone, Copy)]
+struct Reply {
+    rtt_ms: f64,
+    ttl: Option<u8>,
+    dup: bool,
+}
+
+/// What a single run of `ping` came back with.
+#[derive(Debug, Clone, Copy)]
+enum Response {
+    Reply(Reply),
+    Timeout,
+    Unreachable(IcmpError),
+}
+
+/// A probe failure, with how many consecutive probes failed the same way.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ProbeError {
+    pub msg: String,
+    pub count: u64,
+}
+
+impl fmt::Display for ProbeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.msg)?;
+        if self.count > 1 {
+            write!(f, " (x{})", self.count)?;
+        }
+        Ok(())
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -15,80 +197,508 @@ pub struct PingConfig {
     pub host: String,
     pub interval: Duration,
     pub timeout: Duration,
+    pub load: Option<Arc<AtomicBool>>,
+    pub retries: u32,
+    pub count: Option<u64>, // stop after this many probes
+    pub max_inflight: Option<usize>, // Some: probe on a fixed schedule with this many in flight
+    pub jitter: f64, // stretch or shrink each interval by up to this fraction of it
+    pub ping_bin: String, // program run for each probe; looked up in PATH unless it has a `/`
+    pub pause: Option<watch::Receiver<bool>>, // true: send nothing until it goes back to false
+    pub resolve: Resolve,
+    pub reresolve_every: Duration, // how often `Resolve::Periodic` looks the host up again
+}
+
+/// When a probe went out, by the wall clock and the monotonic one.
+type Stamp = (SystemTime, std::time::Instant);
+
+fn stamp() -> Stamp {
+    (SystemTime::now(), std::time::Instant::now())
 }
 
 pub struct Pinger {
-    cfg: PingConfig,
+    cfg: Arc<PingConfig>,
     seq: u64,
+    last_error: Option<ProbeError>,
+    // xorshift state for --jitter; spreading probes out doesn't need a real RNG.
+    rng: u64,
+    pause: Option<watch::Receiver<bool>>,
+    // What `ping` is given: the host itself, or the address it last resolved to.
+    target: String,
+    resolved_at: Option<Instant>,
+    // Where each lookup's address and time go, for the TUI to show.
+    lookups: Option<watch::Sender<Option<Resolved>>>,
+}
+
+/// busybox and older iputils only take whole seconds for `-W`, so ping is given the
+/// timeout rounded up and `run_ping` enforces the exact one: a 300ms timeout kills
+/// ping at 300ms, and a reply slower than that still counts as lost.
+async fn ping_once_linux(cfg: &PingConfig, target: &str) -> Result<Response> {
+    let wait_secs = cfg.timeout.as_secs_f64().ceil().max(1.0) as u64;
+    let mut cmd = Command::new(&cfg.ping_bin);
+    cmd.arg("-n").arg("-c").arg("1")
+        .arg("-W").arg(wait_secs.to_string())
+        .arg(target);
+    run_ping(&mut cmd, cfg.timeout).await?.map_or(Ok(Response::Timeout), |out| parse_output(&out))
+}
+
+/// macOS and FreeBSD take the reply wait in milliseconds with `-W`, so sub-second
+/// timeouts work as given. (`-t` there is a whole-run limit in seconds.)
+#[cfg(any(target_os = "macos", target_os = "freebsd", target_os = "dragonfly"))]
+async fn ping_once_bsd(cfg: &PingConfig, target: &str) -> Result<Response> {
+    let wait_ms = cfg.timeout.as_millis().max(1);
+    let mut cmd = Command::new(&cfg.ping_bin);
+    cmd.arg("-n").arg("-c").arg("1")
+        .arg("-W").arg(wait_ms.to_string())
+        .arg(target);
+    run_ping(&mut cmd, cfg.timeout).await?.map_or(Ok(Response::Timeout), |out| parse_output(&out))
+}
+
+/// Runs one `ping`, killing it if it is still going at the timeout (stuck in DNS, or
+/// waiting out a `-W` rounded up to whole seconds) so a wedged child costs one lost
+/// sample rather than the stream.
+async fn run_ping(cmd: &mut Command, timeout: Duration) -> Result<Option<std::process::Output>> {
+    let program = cmd.as_std().get_program().to_string_lossy().into_owned();
+    let child = cmd
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .kill_on_drop(true)
+        .output();
+    match tokio::time::timeout(timeout, child).await {
+        Ok(out) => Ok(Some(out.with_context(|| format!("failed to execute `{program}`"))?)),
+        Err(_) => Ok(None),
+    }
+}
+
+/// Linux: "From 10.0.0.1 icmp_seq=1 Destination Host Unreachable"
+/// BSD:   "92 bytes from 10.0.0.1: Time to live exceeded"
+fn parse_icmp_error(line: &str) -> Option<IcmpError> {
+    let rest = match line.strip_prefix("From ") {
+        Some(rest) => rest,
+        None => &line[line.find(" bytes from ")? + 12..],
+    };
+    let reason = IcmpReason::parse(rest)?;
+    let addr = rest.split_whitespace().next().unwrap_or_default();
+    let addr = addr.strip_suffix([':', ',']).unwrap_or(addr);
+    // Link-local IPv6 senders come with a zone, e.g. `fe80::1%eth0`.
+    let addr = addr.split('%').next().unwrap_or_default();
+    Some(IcmpError { reason, from: addr.parse().ok() })
+}
+
+/// The RTT in a reply line: the `key=value` (or `key<value`) right before `ms`, as in
+/// `time=12.3 ms`, `time<1ms` and translated builds' `Zeit=0,045 ms`. The summary's
+/// `time 0ms` and `min/avg/max = ... ms` don't match.
+fn parse_rtt(line: &str) -> Option<f64> {
+    let tokens: Vec<&str> = line.split_whitespace().collect();
+    tokens.iter().enumerate().find_map(|(i, tok)| {
+        let num = match tok.strip_suffix("ms") {
+            Some(num) => num,
+            None if tokens.get(i + 1) == Some(&"ms") => tok,
+            None => return None,
+        };
+        let value = &num[num.rfind(['=', '<'])? + 1..];
+        value.replace(',', ".").parse().ok()
+    })
+}
+
+/// The RTT and TTL of the reply in `ping`'s stdout. Normally there is a single reply
+/// line, but when ping reports several times (duplicates, multi-probe runs) the RTT is
+/// their mean.
+fn parse_ping_stdout(s: &str) -> (Option<f64>, Option<u8>) {
+    let times: Vec<f64> = s.lines().filter_map(parse_rtt).collect();
+    let rtt_ms = (!times.is_empty()).then(|| times.iter().sum::<f64>() / times.len() as f64);
+    let ttl = s.lines().find_map(|line| {
+        let lower = line.to_ascii_lowercase();
+        let rest = &lower[lower.find("ttl=")? + 4..];
+        rest[..rest.find(' ').unwrap_or(rest.len())].parse::<u8>().ok()
+    });
+    (rtt_ms, ttl)
+}
+
+/// ping exits 1 when no reply came back; 2 or more, or anything on stderr without an
+/// ICMP error to explain it, means the probe itself failed (a name that didn't
+/// resolve, say), which is reported as an error rather than a plain timeout.
+fn parse_output(out: &std::process::Output) -> Result<Response> {
+    let stdout = String::from_utf8_lossy(&out.stdout);
+    if !out.status.success() {
+        if let Some(e) = stdout.lines().find_map(parse_icmp_error) {
+            return Ok(Response::Unreachable(e));
+        }
+        let stderr = String::from_utf8_lossy(&out.stderr);
+        let first = stderr.lines().map(str::trim).find(|l| !l.is_empty());
+        return match (out.status.code(), first) {
+            (_, Some(line)) => Err(anyhow!("{line}")),
+            (Some(code), None) if code >= 2 => Err(anyhow!("ping exited with status {code}")),
+            _ => Ok(Response::Timeout),
+        };
+    }
+
+    let (rtt_ms, ttl) = parse_ping_stdout(&stdout);
+    let dup = stdout.contains("(DUP!)");
+
+    Ok(match rtt_ms {
+        Some(rtt_ms) => Response::Reply(Reply { rtt_ms, ttl, dup }),
+        None => Response::Timeout,
+    })
+}
+
+#[cfg(target_os = "linux")]
+async fn ping_once(cfg: &PingConfig, target: &str) -> Result<Response> {
+    ping_once_linux(cfg, target).await
+}
+
+#[cfg(any(target_os = "macos", target_os = "freebsd", target_os = "dragonfly"))]
+async fn ping_once(cfg: &PingConfig, target: &str) -> Result<Response> {
+    ping_once_bsd(cfg, target).await
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "macos", target_os = "freebsd", target_os = "dragonfly")))]
+async fn ping_once(_cfg: &PingConfig, _target: &str) -> Result<Response> {
+    Err(anyhow!("Unsupported OS: adjust flags in pinger.rs for Windows and other platforms"))
+}
+
+/// One probe, retrying losses while a full timeout still fits before `deadline`.
+/// Returns the outcome and how many retries it took.
+async fn attempt(cfg: &PingConfig, target: &str, deadline: Instant) -> (Result<Response>, u32) {
+    let mut retries = 0;
+    loop {
+        // ping may wait longer than asked (see ping_once_linux); a reply slower than the
+        // timeout is a loss all the same.
+        let res = ping_once(cfg, target).await.map(|resp| match resp {
+            Response::Reply(r) if r.rtt_ms > cfg.timeout.as_secs_f64() * 1000.0 => Response::Timeout,
+            resp => resp,
+        });
+        match res {
+            // A retry only goes out if it can wait its full timeout within the interval.
+            Ok(Response::Timeout | Response::Unreachable(_))
+                if retries < cfg.retries && Instant::now() + cfg.timeout <= deadline =>
+            {
+                retries += 1
+            }
+            res => return (res, retries),
+        }
+    }
 }
 
 impl Pinger {
     pub fn new(cfg: PingConfig) -> Self {
-        Self { cfg, seq: 0 }
-    }
-
-    async fn ping_once_linux(&mut self) -> Result<PingSample> {
-        let timeout_secs = self.cfg.timeout.as_secs().max(1);
-        let out = Command::new("ping")
-            .arg("-n").arg("-c").arg("1")
-            .arg("-w").arg(timeout_secs.to_string())
-            .arg(&self.cfg.host)
-            .stdout(Stdio::piped())
-            .stderr(Stdio::piped())
-            .output()
-            .await
-            .context("failed to execute `ping`")?;
-
-        self.seq += 1;
-        let seq = self.seq;
-
-        if !out.status.success() {
-            return Ok(PingSample { seq, rtt_ms: None });
-        }
-
-        let stdout = String::from_utf8_lossy(&out.stdout);
-        let rtt_ms = stdout
-            .lines()
-            .find_map(|line| {
-                if let Some(idx) = line.find("time=") {
-                    let rest = &line[idx + 5..];
-                    let end = rest.find(' ').unwrap_or(rest.len());
-                    rest[..end].parse::<f64>().ok()
-                } else {
-                    None
-                }
-            });
+        let seed = SystemTime::now().duration_since(SystemTime::UNIX_EPOCH).unwrap_or_default().as_nanos() as u64;
+        let pause = cfg.pause.clone();
+        let target = cfg.host.clone();
+        Self {
+            cfg: Arc::new(cfg),
+            seq: 0,
+            last_error: None,
+            rng: seed ^ u64::from(std::process::id()) | 1,
+            pause,
+            target,
+            resolved_at: None,
+            lookups: None,
+        }
+    }
+
+    /// Publishes every lookup on `lookups`. A lookup already in it (main's, at
+    /// startup) is used for the first probes rather than repeated.
+    pub fn report_lookups(mut self, lookups: watch::Sender<Option<Resolved>>) -> Self {
+        if let Some(r) = *lookups.borrow() {
+            self.target = r.addr.to_string();
+            self.resolved_at = Some(Instant::now());
+        }
+        self.lookups = Some(lookups);
+        self
+    }
+
+    /// Looks the host up again when `--resolve` says it's time. A failed lookup keeps
+    /// the previous target (the host itself before the first success), so `ping`
+    /// reports the error; per probe it falls back to the host, since the address
+    /// it had may be the very thing that changed.
+    async fn refresh_target(&mut self) {
+        let due = match (self.cfg.resolve, self.resolved_at) {
+            (Resolve::PerProbe, _) | (_, None) => true,
+            (Resolve::Once, Some(_)) => false,
+            (Resolve::Periodic, Some(at)) => at.elapsed() >= self.cfg.reresolve_every,
+        };
+        if !due || self.cfg.host.parse::<IpAddr>().is_ok() {
+            return;
+        }
+        self.resolved_at = Some(Instant::now());
+        let resolved = match dns::resolve(&self.cfg.host).await {
+            Ok(r) => {
+                self.target = r.addr.to_string();
+                Some(r)
+            }
+            Err(_) if self.cfg.resolve == Resolve::PerProbe => {
+                self.target = self.cfg.host.clone();
+                None
+            }
+            Err(_) => return,
+        };
+        if let Some(lookups) = &self.lookups {
+            lookups.send_replace(resolved);
+        }
+    }
+
+    fn is_paused(&self) -> bool {
+        self.pause.as_ref().is_some_and(|p| *p.borrow())
+    }
 
-        Ok(PingSample { seq, rtt_ms })
+    /// Returns once probing isn't paused (right away if it never was).
+    async fn wait_while_paused(&mut self) {
+        if let Some(pause) = self.pause.as_mut() {
+            // An error means the sender is gone and nothing can resume us; carry on.
+            let _ = pause.wait_for(|paused| !paused).await;
+        }
+    }
+
+    /// The wait before the next probe: the interval, moved by a random amount within
+    /// ±`jitter` of it.
+    fn next_interval(&mut self) -> Duration {
+        if self.cfg.jitter <= 0.0 {
+            return self.cfg.interval;
+        }
+        self.rng ^= self.rng << 13;
+        self.rng ^= self.rng >> 7;
+        self.rng ^= self.rng << 17;
+        let unit = (self.rng >> 11) as f64 / (1u64 << 53) as f64; // [0, 1)
+        self.cfg.interval.mul_f64(1.0 + self.cfg.jitter * (2.0 * unit - 1.0))
+    }
+
+    /// Collapses repeats of the same error into a single entry with a count.
+    fn record_error(&mut self, e: anyhow::Error) -> ProbeError {
+        let msg = format!("{e:#}");
+        let err = match self.last_error.take() {
+            Some(prev) if prev.msg == msg => ProbeError { msg, count: prev.count + 1 },
+            _ => ProbeError { msg, count: 1 },
+        };
+        self.last_error = Some(err.clone());
+        err
     }
 
-    #[cfg(target_os = "linux")]
-    async fn ping_once(&mut self) -> Result<PingSample> {
-        self.ping_once_linux().await
+    fn under_load(&self) -> bool {
+        self.cfg.load.as_ref().is_some_and(|a| a.load(Ordering::Relaxed))
     }
 
-    #[cfg(not(target_os = "linux"))]
-    async fn ping_once(&mut self) -> Result<PingSample> {
-        Err(anyhow!("Non-Linux OS: adjust flags in pinger.rs for macOS/Windows"))
+    fn done(&self) -> bool {
+        self.cfg.count.is_some_and(|n| self.seq >= n)
     }
 
-    pub async fn run(mut self, tx: tokio::sync::mpsc::Sender<PingSample>) -> Result<()> {
+    fn sample(&mut self, seq: u64, (ts, sent): Stamp, loaded: bool, res: Result<Response>, retries: u32) -> PingSample {
+        let (reply, outcome, error) = match res {
+            Ok(resp) => {
+                self.last_error = None;
+                match resp {
+                    Response::Reply(r) => (Some(r), Outcome::Reply, None),
+                    Response::Timeout => (None, Outcome::Timeout, None),
+                    Response::Unreachable(e) => (None, Outcome::Unreachable(e), None),
+                }
+            }
+            Err(e) => (None, Outcome::Timeout, Some(self.record_error(e))),
+        };
+        let (rtt_ms, ttl) = (reply.map(|r| r.rtt_ms), reply.and_then(|r| r.ttl));
+        let mut flags = Flags::default();
+        if reply.is_some() && retries > 0 {
+            flags.insert(Flags::RETRIED);
+        }
+        if reply.is_some_and(|r| r.dup) {
+            flags.insert(Flags::DUPLICATE);
+        }
+        PingSample { seq, ts, sent, rtt_ms, error, loaded, retries, ttl, flags, outcome }
+    }
+
+    pub async fn run(self, tx: tokio::sync::mpsc::Sender<PingSample>) -> Result<()> {
+        match self.cfg.max_inflight {
+            Some(max) => self.run_fixed(tx, max).await,
+            None => self.run_sequential(tx).await,
+        }
+    }
+
+    /// Waits for each probe before scheduling the next, so a slow probe delays
+    /// the ones after it but samples always arrive in order.
+    async fn run_sequential(mut self, tx: tokio::sync::mpsc::Sender<PingSample>) -> Result<()> {
         loop {
-            let start = tokio::time::Instant::now();
-            let sample = self.ping_once().await.unwrap_or_else(|_| {
-                self.seq += 1;
-                PingSample { seq: self.seq, rtt_ms: None }
-            });
+            self.wait_while_paused().await;
+            // The lookup counts towards the interval so per-probe resolving keeps the pace.
+            let start = Instant::now();
+            self.refresh_target().await;
+            self.seq += 1;
+            let ts = stamp();
+            let loaded = self.under_load();
+            let interval = self.next_interval();
+            let (res, retries) = attempt(&self.cfg, &self.target, start + interval).await;
+            let sample = self.sample(self.seq, ts, loaded, res, retries);
 
             if tx.send(sample).await.is_err() {
                 break;
             }
+            if self.done() {
+                break;
+            }
 
             let elapsed = start.elapsed();
-            if elapsed < self.cfg.interval {
-                sleep(self.cfg.interval - elapsed).await;
+            if elapsed < interval {
+                sleep(interval - elapsed).await;
             }
         }
         Ok(())
     }
+
+    /// Starts a probe on every tick regardless of earlier ones, with up to `max`
+    /// in flight. The cadence stays fixed, but samples are reported as probes
+    /// complete, so a slow reply can land after replies to later probes. A tick
+    /// that finds every slot busy is recorded as a loss.
+    async fn run_fixed(mut self, tx: tokio::sync::mpsc::Sender<PingSample>, max: usize) -> Result<()> {
+        let slots = Arc::new(Semaphore::new(max));
+        let (done_tx, mut done_rx) = mpsc::unbounded_channel();
+        let mut ticks = interval(self.cfg.interval);
+        ticks.set_missed_tick_behavior(MissedTickBehavior::Skip);
+        let mut newest_done = 0;
+
+        loop {
+            tokio::select! {
+                _ = ticks.tick(), if !self.done() => {
+                    if self.is_paused() {
+                        continue;
+                    }
+                    if self.cfg.jitter > 0.0 {
+                        let next = self.next_interval();
+                        ticks.reset_after(next);
+                    }
+                    self.seq += 1;
+                    let (seq, ts, loaded) = (self.seq, stamp(), self.under_load());
+                    let Ok(slot) = slots.clone().try_acquire_owned() else {
+                        let res = Err(anyhow!("{max} probes still in flight"));
+                        if tx.send(self.sample(seq, ts, loaded, res, 0)).await.is_err() {
+                            break;
+                        }
+                        continue;
+                    };
+                    self.refresh_target().await;
+                    let (cfg, target) = (self.cfg.clone(), self.target.clone());
+                    let done_tx = done_tx.clone();
+                    tokio::spawn(async move {
+                        let (res, retries) = attempt(&cfg, &target, Instant::now() + cfg.interval).await;
+                        // Free the slot first: once the last result is in, the loop below
+                        // ends only if every permit is already back.
+                        drop(slot);
+                        let _ = done_tx.send((seq, ts, loaded, res, retries));
+                    });
+                }
+                Some((seq, ts, loaded, res, retries)) = done_rx.recv() => {
+                    let mut sample = self.sample(seq, ts, loaded, res, retries);
+                    if seq < newest_done {
+                        sample.flags.insert(Flags::REORDERED);
+                    }
+                    newest_done = newest_done.max(seq);
+                    if tx.send(sample).await.is_err() {
+                        break;
+                    }
+                }
+                else => break,
+            }
+            if self.done() && slots.available_permits() == max && done_rx.is_empty() {
+                break;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::parse_ping_stdout;
+
+    #[test]
+    fn iputils() {
+        let out = "PING 1.1.1.1 (1.1.1.1) 56(84) bytes of data.
+64 bytes from 1.1.1.1: icmp_seq=1 ttl=57 time=11.6 ms
+
+--- 1.1.1.1 ping statistics ---
+1 packets transmitted, 1 received, 0% packet loss, time 0ms
+rtt min/avg/max/mdev = 11.600/11.600/11.600/0.000 ms
+";
+        assert_eq!(parse_ping_stdout(out), (Some(11.6), Some(57)));
+    }
+
+    #[test]
+    fn busybox() {
+        let out = "PING 1.1.1.1 (1.1.1.1): 56 data bytes
+64 bytes from 1.1.1.1: seq=0 ttl=57 time=11.620 ms
+
+--- 1.1.1.1 ping statistics ---
+1 packets transmitted, 1 packets received, 0% packet loss
+round-trip min/avg/max = 11.620/11.620/11.620 ms
+";
+        assert_eq!(parse_ping_stdout(out), (Some(11.62), Some(57)));
+    }
+
+    #[test]
+    fn macos() {
+        let out = "PING 1.1.1.1 (1.1.1.1): 56 data bytes
+64 bytes from 1.1.1.1: icmp_seq=0 ttl=57 time=11.620 ms
+
+--- 1.1.1.1 ping statistics ---
+1 packets transmitted, 1 packets received, 0.0% packet loss
+round-trip min/avg/max/stddev = 11.620/11.620/11.620/0.000 ms
+";
+        assert_eq!(parse_ping_stdout(out), (Some(11.62), Some(57)));
+    }
+
+    #[test]
+    fn windows() {
+        let out = "Pinging 1.1.1.1 with 32 bytes of data:
+Reply from 1.1.1.1: bytes=32 time=12ms TTL=57
+
+Ping statistics for 1.1.1.1:
+    Packets: Sent = 1, Received = 1, Lost = 0 (0% loss),
+Approximate round trip times in milli-seconds:
+    Minimum = 12ms, Maximum = 12ms, Average = 12ms
+";
+        assert_eq!(parse_ping_stdout(out), (Some(12.0), Some(57)));
+    }
+
+    #[test]
+    fn under_a_millisecond() {
+        let out = "Reply from 192.168.1.1: bytes=32 time<1ms TTL=64\n";
+        assert_eq!(parse_ping_stdout(out), (Some(1.0), Some(64)));
+    }
+
+    #[test]
+    fn german() {
+        let out = "PING 1.1.1.1 (1.1.1.1) 56(84) Bytes Daten.
+64 Bytes von 1.1.1.1: icmp_seq=1 ttl=57 Zeit=11,6 ms
+
+--- 1.1.1.1 Ping-Statistiken ---
+1 Pakete übertragen, 1 empfangen, 0% Paketverlust, Zeit 0ms
+";
+        assert_eq!(parse_ping_stdout(out), (Some(11.6), Some(57)));
+    }
+
+    #[test]
+    fn french() {
+        let out = "64 octets de 1.1.1.1 : icmp_seq=1 ttl=57 temps=11,6 ms\n";
+        assert_eq!(parse_ping_stdout(out), (Some(11.6), Some(57)));
+    }
+
+    #[test]
+    fn duplicates_are_averaged() {
+        let out = "64 bytes from 1.1.1.1: icmp_seq=1 ttl=57 time=10.0 ms
+64 bytes from 1.1.1.1: icmp_seq=1 ttl=57 time=20.0 ms (DUP!)
+";
+        assert_eq!(parse_ping_stdout(out), (Some(15.0), Some(57)));
+    }
+
+    #[test]
+    fn no_reply() {
+        let out = "PING 10.255.255.1 (10.255.255.1) 56(84) bytes of data.
+
+--- 10.255.255.1 ping statistics ---
+1 packets transmitted, 0 received, 100% packet loss, time 0ms
+";
+        assert_eq!(parse_ping_stdout(out), (None, None));
+    }
+
+    #[test]
+    fn icmp_error_is_not_a_reply() {
+        let out = "From 10.0.0.1 icmp_seq=1 Destination Host Unreachable\n";
+        assert_eq!(parse_ping_stdout(out), (None, None));
+    }
 }
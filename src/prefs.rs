@@ -0,0 +1,107 @@
+use std::fs;
+use std::path::PathBuf;
+
+use anyhow::{Context, Result};
+use clap::ValueEnum;
+
+use crate::ui::{Central, HostShow, View};
+
+/// View settings toggled at runtime, remembered between sessions.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Prefs {
+    pub grid: bool,
+    pub jitter: bool,
+    pub loss_line: bool,
+    pub bars: bool,
+    pub band: bool,
+    pub ttl: bool,
+    pub window: Option<usize>,
+    pub cumulative: bool,
+    pub central: Option<Central>,
+    pub view: View,
+    pub host_show: HostShow,
+}
+
+impl Default for Prefs {
+    fn default() -> Self {
+        Self {
+            grid: false,
+            jitter: false,
+            loss_line: true,
+            bars: false,
+            band: false,
+            ttl: false,
+            window: None,
+            cumulative: false,
+            central: None,
+            view: View::Rtt,
+            host_show: HostShow::Both,
+        }
+    }
+}
+
+/// `$XDG_STATE_HOME/rgping/prefs`, falling back to `~/.local/state/rgping/prefs`.
+fn path() -> Option<PathBuf> {
+    let base = std::env::var_os("XDG_STATE_HOME")
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|h| PathBuf::from(h).join(".local/state")))?;
+    Some(base.join("rgping").join("prefs"))
+}
+
+/// Reads saved preferences; a missing file or unknown/garbled lines fall back to defaults.
+pub fn load() -> Prefs {
+    let mut prefs = Prefs::default();
+    let Some(text) = path().and_then(|p| fs::read_to_string(p).ok()) else {
+        return prefs;
+    };
+    for line in text.lines() {
+        let Some((key, value)) = line.split_once('=') else { continue };
+        let flag = value.trim().parse::<bool>().ok();
+        match (key.trim(), flag) {
+            ("grid", Some(v)) => prefs.grid = v,
+            ("jitter", Some(v)) => prefs.jitter = v,
+            ("loss_line", Some(v)) => prefs.loss_line = v,
+            ("bars", Some(v)) => prefs.bars = v,
+            ("band", Some(v)) => prefs.band = v,
+            ("ttl", Some(v)) => prefs.ttl = v,
+            ("window", _) => prefs.window = value.trim().parse().ok(),
+            ("cumulative", Some(v)) => prefs.cumulative = v,
+            ("central", _) => prefs.central = Central::from_str(value.trim(), true).ok(),
+            ("view", _) => prefs.view = View::from_str(value.trim(), true).unwrap_or(prefs.view),
+            ("host_show", _) => prefs.host_show = HostShow::from_str(value.trim(), true).unwrap_or(prefs.host_show),
+            _ => {}
+        }
+    }
+    prefs
+}
+
+pub fn save(prefs: &Prefs) -> Result<()> {
+    let Some(path) = path() else { return Ok(()) };
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir).with_context(|| format!("failed to create `{}`", dir.display()))?;
+    }
+    let mut text = format!(
+        "grid={}\njitter={}\nloss_line={}\nbars={}\nband={}\nttl={}\ncumulative={}\nview={}\nhost_show={}\n",
+        prefs.grid,
+        prefs.jitter,
+        prefs.loss_line,
+        prefs.bars,
+        prefs.band,
+        prefs.ttl,
+        prefs.cumulative,
+        name(prefs.view),
+        name(prefs.host_show),
+    );
+    if let Some(central) = prefs.central {
+        text.push_str(&format!("central={}\n", name(central)));
+    }
+    if let Some(window) = prefs.window {
+        text.push_str(&format!("window={window}\n"));
+    }
+    fs::write(&path, text).with_context(|| format!("failed to save preferences to `{}`", path.display()))
+}
+
+/// The canonical (first) name clap knows a value by, which `from_str` reads back.
+fn name<T: ValueEnum>(value: T) -> String {
+    value.to_possible_value().map(|v| v.get_name().to_string()).unwrap_or_default()
+}
@@ -0,0 +1,65 @@
+use std::sync::{Arc, Mutex};
+
+use anyhow::{Context, Result};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::broadcast::{self, error::RecvError};
+
+use crate::export::{self, Summary};
+use crate::pinger::PingSample;
+
+// Longest request head read before giving up on a client.
+const MAX_REQUEST: usize = 8 * 1024;
+
+pub async fn bind(addr: &str) -> Result<TcpListener> {
+    TcpListener::bind(addr).await.with_context(|| format!("failed to listen on `{addr}`"))
+}
+
+/// Serves samples as Server-Sent Events: every client gets a `stats` event with the run
+/// so far, then one `sample` event per probe. Any path works; the request is not parsed.
+pub async fn run(listener: TcpListener, samples: broadcast::Sender<PingSample>, summary: Arc<Mutex<Summary>>) -> Result<()> {
+    loop {
+        let (stream, _) = listener.accept().await?;
+        let rx = samples.subscribe();
+        let stats = summary.lock().unwrap().to_json();
+        tokio::spawn(async move {
+            let _ = client(stream, stats, rx).await;
+        });
+    }
+}
+
+async fn client(mut stream: TcpStream, stats: String, mut rx: broadcast::Receiver<PingSample>) -> Result<()> {
+    // Wait for the end of the request head so the client is ready to read the response.
+    let mut head = Vec::new();
+    let mut buf = [0u8; 1024];
+    while !head.windows(4).any(|w| w == b"\r\n\r\n") {
+        let n = stream.read(&mut buf).await?;
+        if n == 0 || head.len() > MAX_REQUEST {
+            return Ok(());
+        }
+        head.extend_from_slice(&buf[..n]);
+    }
+
+    stream.write_all(
+        b"HTTP/1.1 200 OK\r\n\
+          Content-Type: text/event-stream\r\n\
+          Cache-Control: no-cache\r\n\
+          Connection: keep-alive\r\n\
+          Access-Control-Allow-Origin: *\r\n\r\n",
+    ).await?;
+    stream.write_all(format!("event: stats\ndata: {stats}\n\n").as_bytes()).await?;
+
+    loop {
+        match rx.recv().await {
+            Ok(s) => {
+                let event = format!("event: sample\ndata: {}\n\n", export::sample_json(&s));
+                stream.write_all(event.as_bytes()).await?;
+            }
+            // A slow client misses samples rather than holding anyone else up.
+            Err(RecvError::Lagged(_)) => continue,
+            Err(RecvError::Closed) => break,
+        }
+    }
+    stream.shutdown().await?;
+    Ok(())
+}
@@ -1,186 +1,2208 @@
 use std::collections::VecDeque;
+use std::net::IpAddr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::{Duration, Instant, SystemTime};
 
 use crossterm::{
-    event::{self, DisableMouseCapture, EnableMouseCapture, Event, KeyCode, KeyModifiers},
+    cursor,
+    event::{DisableMouseCapture, EnableMouseCapture, Event, EventStream, KeyCode, KeyEvent, KeyModifiers},
     execute,
     terminal::{disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen},
 };
+use futures_util::StreamExt;
 use ratatui::{
-    backend::CrosstermBackend,
-    Terminal,
-    layout::{Constraint, Direction, Layout},
+    backend::{Backend, CrosstermBackend},
+    Frame, Terminal, TerminalOptions, Viewport,
+    layout::{Alignment, Constraint, Direction, Layout, Rect},
     style::{Color, Modifier, Style},
+    symbols::Marker,
     text::{Span, Line},
-    widgets::{Block, Borders, Paragraph, Chart, Axis, Dataset, GraphType},
+    widgets::{Bar, BarChart, BarGroup, Block, Borders, Clear, Paragraph, Chart, Axis, Dataset, GraphType, Sparkline},
 };
-use crate::pinger::PingSample;
+use clap::ValueEnum;
+use tokio::sync::watch;
+use tokio::time::MissedTickBehavior;
+
+use crate::color::{ColorDepth, Palette, Severity};
+use crate::diag::ChannelStats;
+use crate::dns::Resolved;
+use crate::export::{Anonymizer, Note, Summary, TsFormat};
+use crate::pinger::{Flags, IcmpError, Outcome, PingSample, ProbeError};
+use crate::prefs::{self, Prefs};
 
 pub struct UiConfig {
     pub host: String,
+    pub label: Option<String>, // shown in the header instead of the host
+    pub title: String, // title of the latency chart
     pub history: usize,
+    pub backlog: usize,
+    pub max_samples: usize, // single samples kept before older ones are averaged in groups of FOLD
+    pub load: bool,
+    pub central: Central,
+    pub median_filter: usize,
+    pub y_ticks: usize,
+    pub y_headroom: f64, // percent added above the highest RTT
+    pub last_avg: usize, // footer's "last" is the mean of this many newest samples
+    pub warmup: u64, // leave this many initial samples out of the stats
+    pub retries: bool,
+    pub unreachable_as: UnreachableAs,
+    pub marker: Marker,
+    pub warn_ms: f64,
+    pub crit_ms: f64,
+    pub loss_warn: f64,
+    pub loss_crit: f64,
+    pub palette: Palette, // severity colors, and shapes with the colorblind one
+    pub resolved: watch::Receiver<Option<Resolved>>, // the pinger's latest lookup
+    pub literal: bool, // host is an IP address, so no lookup was done
+    pub ptr: Arc<OnceLock<String>>, // reverse DNS name, set by a background lookup with --rdns
+    pub source: Option<IpAddr>, // local address probes go out from, per the routing table
+    pub prefs: Prefs,
+    pub warnings: Vec<String>, // startup warnings, also printed to stderr before the TUI starts
+    pub color_depth: ColorDepth,
+    pub time_axis: bool, // x-axis in seconds rather than samples, for flood mode
+    pub channel: Arc<ChannelStats>,
+    pub interval: Duration,
+    pub countdown: bool, // show the time left until the next probe in the header
+    pub ui_fps: Option<f64>, // redraws per second with no new samples; None picks from `countdown`
+    pub altscreen: bool, // false: draw inline so the last frame stays in the scrollback
+    pub stop: Arc<AtomicBool>, // set by main on SIGTERM so the terminal gets restored
+    pub pause: watch::Sender<bool>, // true stops the pinger sending probes
+    pub summary: Arc<Mutex<Summary>>, // notes typed in are added to the --summary-json output
+    pub anon: Anonymizer, // pseudonyms for the addresses shown, with --anonymize
+}
+
+const MIN_WINDOW: usize = 10;
+// Most samples the backlog can keep: over three months at one probe a second.
+pub const MAX_BACKLOG: usize = 10_000_000;
+
+// Samples averaged into each retained entry once more than --max-samples are kept.
+const FOLD: usize = 10;
+
+// Shortest time between two draws while samples stream in.
+const FRAME: Duration = Duration::from_millis(33);
+
+// Below this many columns and rows the layout doesn't fit and only a notice is drawn.
+// Key hints for the footer, quitting first so it's never the one cut off.
+const HINTS: &[&str] = &[
+    "quit: q / Esc / Ctrl-C", "scroll: ←/→/End", "zoom: +/-", "grid: g", "jitter: j", "loss: l", "bars: b",
+    "band: s", "ttl: t", "stat: c", "view: v", "host/ip: n", "pause probing: p", "note: m", "events: e",
+    "window/all: w", "dump: d", "keep: H/h",
+];
+const SHORT_HINTS: &[&str] = &["q", "←→ End", "+-", "g j l b s t c v n p m e w d", "H h"];
+// Most footer lines the long hints may take before the short ones are used instead.
+const HINT_LINES: usize = 2;
+const MIN_WIDTH: u16 = 40;
+const MIN_HEIGHT: u16 = 12;
+
+// Longest note that can be typed in for a chart marker.
+const NOTE_MAX: usize = 40;
+
+// How long the header's reply dot stays lit after a reply.
+const PULSE: Duration = Duration::from_millis(300);
+
+// Loaded RTT this much above idle RTT is reported as bufferbloat.
+const BLOAT_MS: f64 = 60.0;
+
+// Dim enough not to compete with the data; downgraded on terminals without truecolor.
+const GRID_COLOR: Color = Color::Rgb(70, 70, 70);
+
+// Number of samples each point of the loss sparkline covers.
+const LOSS_SUBWINDOW: usize = 10;
+
+// Below this many replies a confidence interval says more about luck than latency.
+const MIN_CI_SAMPLES: usize = 5;
+
+// Number of preceding replies the baseline band's rolling mean and stddev cover.
+const BAND_WINDOW: usize = 20;
+
+// Fill for the ±1 stddev band; dim so the RTT line stays readable on top of it.
+const BAND_COLOR: Color = Color::Rgb(40, 60, 90);
+
+// Consecutive replies with a new TTL needed before it counts as a route change.
+const TTL_DEBOUNCE: usize = 3;
+
+// How far the wall clock may drift from the monotonic one between two samples before
+// it counts as a clock step or a suspend.
+const CLOCK_JUMP: Duration = Duration::from_secs(2);
+
+// Trailing span of sample timestamps the achieved probe rate is measured over.
+const RATE_WINDOW: Duration = Duration::from_secs(5);
+
+// Gaps between consecutive sends averaged for the interval drift, and how far (in
+// percent of the interval) the average may stray before it is highlighted.
+const DRIFT_GAPS: usize = 10;
+const DRIFT_WARN_PCT: f64 = 10.0;
+
+// Number of events kept in the event log.
+const MAX_EVENTS: usize = 50;
+
+// Weight of the newest reply in the footer's EWMA.
+const EWMA_ALPHA: f64 = 0.2;
+
+// Floor for the geometric mean so sub-ms (or zero) RTTs don't send the log to -inf.
+const GEOMEAN_FLOOR_MS: f64 = 0.001;
+
+/// Which statistic the footer shows as its central value.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Central {
+    #[value(alias = "mean")]
+    Arith,
+    Geo,
+    Median,
+    P95,
+    Ewma,
+}
+
+/// Whether ICMP unreachable replies count as loss or are tallied on their own.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum UnreachableAs {
+    Loss,
+    Separate,
+}
+
+/// Glyphs used to draw the chart lines; see `symbols::Marker`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum MarkerKind {
+    Braille,
+    Dot,
+    Block,
+    Bar,
+}
+
+impl From<MarkerKind> for Marker {
+    fn from(m: MarkerKind) -> Self {
+        match m {
+            MarkerKind::Braille => Marker::Braille,
+            MarkerKind::Dot => Marker::Dot,
+            MarkerKind::Block => Marker::Block,
+            MarkerKind::Bar => Marker::Bar,
+        }
+    }
+}
+
+impl Central {
+    fn label(self) -> &'static str {
+        match self {
+            Central::Arith => "avg",
+            Central::Geo => "geo",
+            Central::Median => "median",
+            Central::P95 => "p95",
+            Central::Ewma => "ewma",
+        }
+    }
+
+    fn short_label(self) -> &'static str {
+        match self {
+            Central::Arith => "A",
+            Central::Geo => "G",
+            Central::Median => "M",
+            Central::P95 => "P95",
+            Central::Ewma => "E",
+        }
+    }
+
+    /// Whether it needs every sample rather than running totals, so with stats since
+    /// start it can only cover the kept backlog.
+    fn needs_samples(self) -> bool {
+        matches!(self, Central::Median | Central::P95)
+    }
+
+    fn next(self) -> Self {
+        match self {
+            Central::Arith => Central::Geo,
+            Central::Geo => Central::Median,
+            Central::Median => Central::P95,
+            Central::P95 => Central::Ewma,
+            Central::Ewma => Central::Arith,
+        }
+    }
+}
+
+/// What the main chart plots: the RTTs themselves, the change between consecutive
+/// replies, or the rolling loss rate.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum View {
+    Rtt,
+    Jitter,
+    Loss,
+}
+
+impl View {
+    fn next(self) -> Self {
+        match self {
+            View::Rtt => View::Jitter,
+            View::Jitter => View::Loss,
+            View::Loss => View::Rtt,
+        }
+    }
+}
+
+/// How the header names the target: as given, by address, or both.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum HostShow {
+    Both,
+    Name,
+    Addr,
+}
+
+impl HostShow {
+    fn next(self) -> Self {
+        match self {
+            HostShow::Both => HostShow::Name,
+            HostShow::Name => HostShow::Addr,
+            HostShow::Addr => HostShow::Both,
+        }
+    }
+}
+
+/// A retained sample: its RTT plus what the chart needs to know about it.
+#[derive(Debug, Clone, Copy)]
+struct Entry {
+    seq: u64,
+    rtt: Option<f64>,
+    loaded: bool,
+    ts: SystemTime,
+    sent: Instant,
+    ttl: Option<u8>,
+    ttl_change: bool, // first sample seen with a new, confirmed TTL
+    clock_jump: bool, // the wall clock stepped (or the machine slept) since the previous sample
+    flags: Flags,
+    outcome: Outcome,
+    warmup: bool, // one of the first --warmup samples, plotted but left out of the stats
+    span: usize, // samples this entry stands for; more than 1 once older ones are folded
+}
+
+impl Entry {
+    /// One entry standing for a run of consecutive ones: the mean of their replies,
+    /// lost only if all of them were.
+    fn fold(group: &[Entry]) -> Entry {
+        let first = group[0];
+        let replies: Vec<f64> = group.iter().filter_map(|e| e.rtt).collect();
+        let rtt = (!replies.is_empty()).then(|| replies.iter().sum::<f64>() / replies.len() as f64);
+        let mut flags = Flags::default();
+        for e in group {
+            flags.insert(e.flags);
+        }
+        Entry {
+            rtt,
+            loaded: group.iter().any(|e| e.loaded),
+            ttl: group.iter().rev().find_map(|e| e.ttl),
+            ttl_change: group.iter().any(|e| e.ttl_change),
+            clock_jump: group.iter().any(|e| e.clock_jump),
+            flags,
+            outcome: if rtt.is_some() { Outcome::Reply } else { first.outcome },
+            warmup: group.iter().all(|e| e.warmup),
+            span: group.iter().map(|e| e.span).sum(),
+            ..first
+        }
+    }
+}
+
+/// Something worth noting that happened during the run, like a TTL change.
+#[derive(Debug, Clone)]
+struct LogEntry {
+    ts: SystemTime,
+    msg: String,
+}
+
+/// Running totals over every reply since start, for the cumulative footer stats.
+#[derive(Debug, Clone, Copy, Default)]
+struct Totals {
+    n: usize,
+    // Welford's running mean and sum of squared deviations.
+    mean: f64,
+    m2: f64,
+    min: Option<f64>,
+    max: Option<f64>,
+    log_sum: f64,
+    ewma: Option<f64>,
+}
+
+impl Totals {
+    fn push(&mut self, v: f64) {
+        self.n += 1;
+        let delta = v - self.mean;
+        self.mean += delta / self.n as f64;
+        self.m2 += delta * (v - self.mean);
+        self.min = Some(self.min.map_or(v, |m| m.min(v)));
+        self.max = Some(self.max.map_or(v, |m| m.max(v)));
+        self.log_sum += v.max(GEOMEAN_FLOOR_MS).ln();
+        self.ewma = Some(self.ewma.map_or(v, |e| e + EWMA_ALPHA * (v - e)));
+    }
 }
 
 pub struct UiState {
-    rtts: VecDeque<Option<f64>>,
+    rtts: VecDeque<Entry>,
+    // Number of samples the chart displays; starts at `history` and changes with zoom.
+    window: usize,
+    // Samples retained, however many entries they are folded into.
+    backlog: usize,
+    samples: usize,
+    // At most `max_raw` of the newest entries hold a single sample; older ones are
+    // folded `FOLD` at a time. `raw` is how many currently do.
+    max_raw: usize,
+    raw: usize,
+    // Samples between the right edge of the chart and the newest sample; 0 means live.
+    scroll: usize,
+    last_seq: u64,
     total: u64,
     lost: u64,
+    unreachable: u64,
+    // The first this many samples are warm-up; `warmed` is how many of them have come in.
+    warmup: u64,
+    warmed: u64,
+    // Leave ICMP unreachable replies out of the loss figure and count them separately.
+    separate_unreachable: bool,
+    // The ICMP error the latest sample got, so a run of the same one is logged once.
+    icmp: Option<IcmpError>,
+    anon: Anonymizer,
+    retried: u64,
     last: Option<f64>,
+    error: Option<ProbeError>,
+    ttl: Option<u8>,
+    // A different TTL that has shown up recently: the TTL, the seq it first showed up
+    // at and how many replies have carried it since, with none of the old TTL among them.
+    ttl_pending: Option<(u8, u64, usize)>,
+    events: VecDeque<LogEntry>,
+    notes: Vec<Note>,
+    // Stats cover every reply since start rather than the most recent window.
+    cumulative: bool,
+    totals: Totals,
 }
 
 impl UiState {
-    pub fn new(history: usize) -> Self {
+    pub fn new(history: usize, backlog: usize) -> Self {
+        let history = history.min(MAX_BACKLOG);
+        let backlog = backlog.clamp(history, MAX_BACKLOG);
         Self {
-            rtts: VecDeque::with_capacity(history),
+            // Grows as samples come in rather than reserving the whole backlog, most of
+            // which may end up folded.
+            rtts: VecDeque::new(),
+            window: history,
+            backlog,
+            samples: 0,
+            max_raw: usize::MAX,
+            raw: 0,
+            scroll: 0,
+            last_seq: 0,
             total: 0,
             lost: 0,
+            unreachable: 0,
+            separate_unreachable: false,
+            retried: 0,
             last: None,
+            error: None,
+            ttl: None,
+            ttl_pending: None,
+            icmp: None,
+            anon: Anonymizer::default(),
+            warmup: 0,
+            warmed: 0,
+            events: VecDeque::new(),
+            notes: Vec::new(),
+            cumulative: false,
+            totals: Totals::default(),
         }
     }
 
-    pub fn push(&mut self, rtt: Option<f64>, history: usize) {
+    pub fn push(&mut self, s: &PingSample) {
+        let rtt = s.rtt_ms;
+        let warmup = s.seq <= self.warmup;
         self.total += 1;
-        if rtt.is_none() { self.lost += 1; }
+        if warmup { self.warmed += 1; }
+        if rtt.is_none() && !warmup { self.lost += 1; }
+        if let Outcome::Unreachable(e) = s.outcome {
+            if !warmup { self.unreachable += 1; }
+            if self.icmp != Some(e) {
+                self.log(s.ts, format!("{} at #{}", self.anon.icmp(&e), s.seq));
+            }
+        }
+        self.icmp = match s.outcome {
+            Outcome::Unreachable(e) => Some(e),
+            _ => None,
+        };
+        if rtt.is_some() && s.retries > 0 { self.retried += 1; }
+        if let Some(v) = rtt.filter(|_| !warmup) { self.totals.push(v); }
         self.last = rtt;
-        if self.rtts.len() == history {
-            self.rtts.pop_front();
+        self.last_seq = s.seq;
+        self.error = s.error.clone();
+        let prev = self.rtts.back().copied();
+        let clock_jump = prev.is_some_and(|prev| self.clock_jumped(&prev, s));
+        while self.samples >= self.backlog {
+            self.pop_oldest();
+        }
+        self.rtts.push_back(Entry {
+            seq: s.seq,
+            rtt,
+            loaded: s.loaded,
+            ts: s.ts,
+            sent: s.sent,
+            ttl: s.ttl,
+            ttl_change: false,
+            clock_jump,
+            flags: s.flags,
+            outcome: s.outcome,
+            warmup,
+            span: 1,
+        });
+        self.samples += 1;
+        self.raw += 1;
+        if self.raw > self.max_raw {
+            self.fold_oldest();
+        }
+        if let Some(ttl) = s.ttl {
+            self.track_ttl(ttl, s);
+        }
+        // Keep a scrolled-back view pinned to the same samples while new ones arrive.
+        if self.scroll > 0 {
+            self.scroll = (self.scroll + 1).min(self.max_scroll());
+        }
+    }
+
+    /// Whether the wall clock moved differently from the monotonic clock between `prev`
+    /// and `s` by more than `CLOCK_JUMP`, and if so logs by how much. The monotonic clock
+    /// doesn't count time spent suspended, so a resume shows up as a forward jump.
+    fn clock_jumped(&mut self, prev: &Entry, s: &PingSample) -> bool {
+        let signed = |later: Result<Duration, Duration>| match later {
+            Ok(d) => d.as_secs_f64(),
+            Err(d) => -d.as_secs_f64(),
+        };
+        let wall = signed(s.ts.duration_since(prev.ts).map_err(|e| e.duration()));
+        let mono = signed(s.sent.checked_duration_since(prev.sent).ok_or_else(|| prev.sent - s.sent));
+        let jump = wall - mono;
+        if jump.abs() <= CLOCK_JUMP.as_secs_f64() {
+            return false;
+        }
+        self.log(s.ts, format!("clock jumped {jump:+.1}s before #{} (clock step or suspend)", s.seq));
+        true
+    }
+
+    /// Accepts a new TTL once `TTL_DEBOUNCE` replies in a row carry it, so a single odd
+    /// reply isn't reported as a route change.
+    fn track_ttl(&mut self, ttl: u8, s: &PingSample) {
+        let Some(cur) = self.ttl else {
+            self.ttl = Some(ttl);
+            return;
+        };
+        if ttl == cur {
+            self.ttl_pending = None;
+            return;
+        }
+        let (since, n) = match self.ttl_pending {
+            Some((t, since, n)) if t == ttl => (since, n + 1),
+            _ => (s.seq, 1),
+        };
+        if n < TTL_DEBOUNCE {
+            self.ttl_pending = Some((ttl, since, n));
+            return;
+        }
+        self.ttl = Some(ttl);
+        self.ttl_pending = None;
+        // By seq rather than position: reordered or skipped samples and folded entries
+        // leave gaps between positions and seqs.
+        if let Some(e) = self.rtts.iter_mut().rev().find(|e| (e.seq..e.seq + e.span as u64).contains(&since)) {
+            e.ttl_change = true;
+        }
+        self.log(s.ts, format!("TTL {cur} → {ttl} at #{since}, route may have changed"));
+    }
+
+    fn log(&mut self, ts: SystemTime, msg: String) {
+        if self.events.len() == MAX_EVENTS {
+            self.events.pop_front();
+        }
+        self.events.push_back(LogEntry { ts, msg });
+    }
+
+    fn pop_oldest(&mut self) {
+        if let Some(e) = self.rtts.pop_front() {
+            self.samples -= e.span;
+            if self.rtts.len() < self.raw {
+                self.raw -= 1;
+            }
+        }
+    }
+
+    /// Folds the oldest `FOLD` single-sample entries into one.
+    fn fold_oldest(&mut self) {
+        let start = self.rtts.len() - self.raw;
+        let n = FOLD.min(self.raw);
+        let group: Vec<Entry> = self.rtts.drain(start..start + n).collect();
+        self.rtts.insert(start, Entry::fold(&group));
+        self.raw -= n;
+        self.scroll = self.scroll.min(self.max_scroll());
+    }
+
+    /// Caps how many samples are kept one per entry; beyond that, older samples are
+    /// averaged `FOLD` to an entry, so scrolling far back shows coarser detail but a
+    /// large backlog takes about a tenth of the memory.
+    pub fn set_max_samples(&mut self, n: usize) {
+        self.max_raw = n.max(MIN_WINDOW);
+        while self.raw > self.max_raw {
+            self.fold_oldest();
+        }
+        self.rtts.shrink_to(self.max_entries());
+    }
+
+    /// The most entries `backlog` samples can take up.
+    fn max_entries(&self) -> usize {
+        match self.backlog.checked_sub(self.max_raw) {
+            Some(folded) => self.max_raw + folded.div_ceil(FOLD),
+            None => self.backlog,
+        }
+    }
+
+    fn max_scroll(&self) -> usize {
+        self.rtts.len().saturating_sub(self.window)
+    }
+
+    pub fn window(&self) -> usize {
+        self.window
+    }
+
+    pub fn backlog(&self) -> usize {
+        self.backlog
+    }
+
+    /// Changes how many samples are retained, up to `MAX_BACKLOG`. Growing keeps
+    /// everything and allocates only as samples arrive; shrinking drops the oldest
+    /// samples and narrows the window if it no longer fits.
+    pub fn resize_backlog(&mut self, backlog: usize) {
+        self.backlog = backlog.clamp(MIN_WINDOW, MAX_BACKLOG);
+        while self.samples > self.backlog {
+            self.pop_oldest();
+        }
+        self.rtts.shrink_to(self.max_entries());
+        self.window = self.window.min(self.backlog);
+        self.scroll = self.scroll.min(self.max_scroll());
+    }
+
+    pub fn zoom_in(&mut self) {
+        self.window = (self.window / 2).max(MIN_WINDOW.min(self.backlog));
+    }
+
+    pub fn zoom_out(&mut self) {
+        self.window = (self.window * 2).min(self.backlog);
+        self.scroll = self.scroll.min(self.max_scroll());
+    }
+
+    pub fn scroll_back(&mut self, n: usize) {
+        self.scroll = (self.scroll + n).min(self.max_scroll());
+    }
+
+    pub fn scroll_forward(&mut self, n: usize) {
+        self.scroll = self.scroll.saturating_sub(n);
+    }
+
+    pub fn follow(&mut self) {
+        self.scroll = 0;
+    }
+
+    pub fn is_live(&self) -> bool {
+        self.scroll == 0
+    }
+
+    /// The most recent `window` samples, regardless of scroll position.
+    fn recent(&self) -> impl Iterator<Item = &Entry> {
+        self.rtts.iter().skip(self.rtts.len().saturating_sub(self.window))
+    }
+
+    /// The `window`-sized slice of the backlog currently shown on the chart.
+    fn visible(&self) -> impl Iterator<Item = &Entry> {
+        let end = self.rtts.len() - self.scroll;
+        let start = end.saturating_sub(self.window);
+        self.rtts.range(start..end)
+    }
+
+    /// Sequence numbers of the first and last visible samples.
+    fn visible_seq(&self) -> (u64, u64) {
+        let mut visible = self.visible();
+        let first = visible.next();
+        let last = visible.last().or(first);
+        match (first, last) {
+            // A folded entry stands for the `span` samples from its own seq on.
+            (Some(first), Some(last)) => (first.seq, last.seq + last.span as u64 - 1),
+            _ => (0, 0),
         }
-        self.rtts.push_back(rtt);
+    }
+
+    /// Successful RTTs in the most recent window.
+    fn recent_rtts(&self) -> impl Iterator<Item = f64> + '_ {
+        self.recent().filter(|e| !e.warmup).filter_map(|e| e.rtt)
+    }
+
+    /// RTTs that order statistics (median, percentiles) are taken over: the window, or
+    /// everything retained when stats are cumulative, since those can't be kept as sums.
+    fn stat_rtts(&self) -> impl Iterator<Item = f64> + '_ {
+        let skip = if self.cumulative { 0 } else { self.rtts.len().saturating_sub(self.window) };
+        self.rtts.iter().skip(skip).filter(|e| !e.warmup).filter_map(|e| e.rtt)
+    }
+
+    pub fn is_cumulative(&self) -> bool {
+        self.cumulative
+    }
+
+    pub fn toggle_cumulative(&mut self) {
+        self.cumulative = !self.cumulative;
     }
 
     pub fn avg(&self) -> Option<f64> {
+        if self.cumulative {
+            return (self.totals.n > 0).then_some(self.totals.mean);
+        }
         let mut sum = 0.0;
         let mut cnt = 0;
-        for v in self.rtts.iter().flatten() {
-            sum += *v;
+        for v in self.recent_rtts() {
+            sum += v;
             cnt += 1;
         }
         (cnt > 0).then(|| sum / cnt as f64)
     }
 
+    pub fn min(&self) -> Option<f64> {
+        if self.cumulative {
+            return self.totals.min;
+        }
+        self.recent_rtts().reduce(f64::min)
+    }
+
+    pub fn max(&self) -> Option<f64> {
+        if self.cumulative {
+            return self.totals.max;
+        }
+        self.recent_rtts().reduce(f64::max)
+    }
+
+    /// Sample standard deviation of the window's RTTs.
+    pub fn stddev(&self) -> Option<f64> {
+        if self.cumulative {
+            let t = &self.totals;
+            return (t.n > 1).then(|| (t.m2 / (t.n - 1) as f64).sqrt());
+        }
+        let mean = self.avg()?;
+        let (sum_sq, n) = self.recent_rtts().fold((0.0, 0usize), |(acc, n), v| (acc + (v - mean).powi(2), n + 1));
+        (n > 1).then(|| (sum_sq / (n - 1) as f64).sqrt())
+    }
+
+    /// Half-width of the 95% confidence interval for the mean RTT.
+    pub fn mean_ci(&self) -> Option<f64> {
+        let n = if self.cumulative { self.totals.n } else { self.recent_rtts().count() };
+        if n < MIN_CI_SAMPLES {
+            return None;
+        }
+        Some(1.96 * self.stddev()? / (n as f64).sqrt())
+    }
+
+    pub fn geomean(&self) -> Option<f64> {
+        if self.cumulative {
+            let t = &self.totals;
+            return (t.n > 0).then(|| (t.log_sum / t.n as f64).exp());
+        }
+        let mut log_sum = 0.0;
+        let mut cnt = 0;
+        for v in self.recent_rtts() {
+            log_sum += v.max(GEOMEAN_FLOOR_MS).ln();
+            cnt += 1;
+        }
+        (cnt > 0).then(|| (log_sum / cnt as f64).exp())
+    }
+
+    pub fn median(&self) -> Option<f64> {
+        median_of(&mut self.stat_rtts().collect::<Vec<_>>())
+    }
+
+    /// Nearest-rank percentile of the window's RTTs.
+    pub fn percentile(&self, p: f64) -> Option<f64> {
+        let mut v: Vec<f64> = self.stat_rtts().collect();
+        if v.is_empty() {
+            return None;
+        }
+        v.sort_by(f64::total_cmp);
+        let rank = (p / 100.0 * v.len() as f64).ceil() as usize;
+        Some(v[rank.clamp(1, v.len()) - 1])
+    }
+
+    /// Exponentially weighted moving average over the window, oldest reply first.
+    pub fn ewma(&self) -> Option<f64> {
+        if self.cumulative {
+            return self.totals.ewma;
+        }
+        self.recent_rtts().reduce(|acc, v| acc + EWMA_ALPHA * (v - acc))
+    }
+
+    pub fn central(&self, c: Central) -> Option<f64> {
+        match c {
+            Central::Arith => self.avg(),
+            Central::Geo => self.geomean(),
+            Central::Median => self.median(),
+            Central::P95 => self.percentile(95.0),
+            Central::Ewma => self.ewma(),
+        }
+    }
+
+    /// Average over the window of only the samples taken with (or without) load applied.
+    pub fn avg_loaded(&self, loaded: bool) -> Option<f64> {
+        let mut sum = 0.0;
+        let mut cnt = 0;
+        for v in self.recent().filter(|e| e.loaded == loaded && !e.warmup).filter_map(|e| e.rtt) {
+            sum += v;
+            cnt += 1;
+        }
+        (cnt > 0).then(|| sum / cnt as f64)
+    }
+
+    pub fn last(&self) -> Option<f64> {
+        self.last
+    }
+
+    /// Mean of the replies among the newest `n` samples, or `None` if the newest was lost
+    /// so a fresh timeout still shows straight away.
+    pub fn last_avg(&self, n: usize) -> Option<f64> {
+        self.last?;
+        let newest = self.rtts.iter().rev().take(n.max(1)).filter_map(|e| e.rtt);
+        let (sum, cnt) = newest.fold((0.0, 0), |(sum, cnt), v| (sum + v, cnt + 1));
+        Some(sum / cnt as f64)
+    }
+
+    pub fn total(&self) -> u64 {
+        self.total
+    }
+
+    /// Whether the window's losses come mostly in runs of consecutive drops ("bursty")
+    /// or mostly one at a time ("isolated"); `None` without losses.
+    pub fn loss_pattern(&self) -> Option<&'static str> {
+        let (mut isolated, mut bursty, mut run) = (0, 0, 0);
+        let lost = |e: &Entry| match e.outcome {
+            Outcome::Reply => false,
+            Outcome::Timeout => true,
+            Outcome::Unreachable(_) => !self.separate_unreachable,
+        };
+        for lost in self.recent().filter(|e| !e.warmup).map(lost).chain([false]) {
+            if lost {
+                run += 1;
+                continue;
+            }
+            match run {
+                0 => {}
+                1 => isolated += 1,
+                n => bursty += n,
+            }
+            run = 0;
+        }
+        match (isolated, bursty) {
+            (0, 0) => None,
+            (i, b) if b > i => Some("bursty"),
+            _ => Some("isolated"),
+        }
+    }
+
+    /// Writes the samples currently on the chart to a CSV file in the working directory
+    /// and notes where in the event log.
+    pub fn dump_visible(&mut self, host: &str) {
+        let now = SystemTime::now();
+        let stamp = chrono::DateTime::<chrono::Local>::from(now).format("%Y%m%d-%H%M%S");
+        let path = format!("rgping-{}-{stamp}.csv", host.replace([':', '/'], "_"));
+        let mut text = String::from("seq,timestamp,rtt_ms,status,note\n");
+        let mut n = 0;
+        for e in self.visible() {
+            let status = match e.outcome {
+                Outcome::Reply => "reply",
+                Outcome::Timeout => "timeout",
+                Outcome::Unreachable(_) => "unreachable",
+            };
+            let rtt = e.rtt.map(|v| v.to_string()).unwrap_or_default();
+            let note = self.notes.iter()
+                .filter(|n| n.seq == e.seq)
+                .map(|n| format!("\"{}\"", n.text.replace('"', "\"\"")))
+                .collect::<Vec<_>>()
+                .join(" ");
+            text.push_str(&format!("{},{},{rtt},{status},{note}\n", e.seq, TsFormat::Rfc3339.format(e.ts)));
+            n += 1;
+        }
+        let msg = match std::fs::write(&path, text) {
+            Ok(()) => format!("dumped {n} samples to {path}"),
+            Err(e) => format!("dump to {path} failed: {e}"),
+        };
+        self.log(now, msg);
+    }
+
+    /// Probes per second actually sent over the last `RATE_WINDOW`, from the samples'
+    /// monotonic send times.
+    pub fn rate(&self) -> Option<f64> {
+        let newest = self.rtts.back()?.sent;
+        let since = newest.checked_sub(RATE_WINDOW)?;
+        let (n, oldest) = self.rtts.iter().rev()
+            .take_while(|e| e.sent >= since)
+            .fold((0, newest), |(n, _), e| (n + 1, e.sent));
+        let span = newest.duration_since(oldest).as_secs_f64();
+        (n > 1 && span > 0.0).then(|| (n - 1) as f64 / span)
+    }
+
+    /// The mean time between the sends of the last `DRIFT_GAPS` + 1 samples, to set
+    /// against the configured interval.
+    pub fn mean_gap(&self) -> Option<Duration> {
+        let recent: Vec<Instant> = self.rtts.iter().rev().take(DRIFT_GAPS + 1).map(|e| e.sent).collect();
+        let (newest, oldest) = (recent.first()?, recent.last()?);
+        (recent.len() > 1).then(|| newest.duration_since(*oldest) / (recent.len() - 1) as u32)
+    }
+
+    pub fn add_note(&mut self, note: Note) {
+        self.log(note.ts, format!("note at #{}: {}", note.seq, note.text));
+        self.notes.push(note);
+    }
+
+    /// Leaves the first `n` samples out of every statistic.
+    pub fn set_warmup(&mut self, n: u64) {
+        self.warmup = n;
+    }
+
+    /// Warm-up samples received so far.
+    pub fn warmed(&self) -> u64 {
+        self.warmed
+    }
+
+    pub fn set_anonymizer(&mut self, anon: Anonymizer) {
+        self.anon = anon;
+    }
+
+    pub fn set_separate_unreachable(&mut self, separate: bool) {
+        self.separate_unreachable = separate;
+    }
+
+    /// Loss since start, or over the window unless stats are cumulative.
     pub fn loss_pct(&self) -> f64 {
-        if self.total == 0 { 0.0 } else { (self.lost as f64) * 100.0 / (self.total as f64) }
+        let (lost, total) = if self.cumulative {
+            let lost = if self.separate_unreachable { self.lost - self.unreachable } else { self.lost };
+            (lost, self.total - self.warmed)
+        } else {
+            // A folded entry counts as lost only if each of its samples was.
+            self.recent().filter(|e| !e.warmup).fold((0, 0), |(lost, total), e| {
+                let counted = e.rtt.is_none()
+                    && !(self.separate_unreachable && matches!(e.outcome, Outcome::Unreachable(_)));
+                (lost + if counted { e.span as u64 } else { 0 }, total + e.span as u64)
+            })
+        };
+        if total == 0 { 0.0 } else { (lost as f64) * 100.0 / (total as f64) }
     }
 
-    fn y_max(&self) -> f64 {
+    /// Top of the RTT axis: the highest visible RTT plus `headroom_pct` percent, rounded up.
+    fn y_max(&self, headroom_pct: f64) -> f64 {
         let mut m = 10.0;
-        for v in self.rtts.iter().flatten() {
-            if *v > m { m = *v; }
+        for v in self.visible().filter_map(|e| e.rtt) {
+            if v > m { m = v; }
         }
-        (m * 1.20).ceil()
+        (m * (1.0 + headroom_pct / 100.0)).ceil()
     }
 }
 
 pub struct Ui {
     cfg: UiConfig,
     state: UiState,
+    grid: bool,
+    jitter: bool,
+    loss_line: bool,
+    bars: bool,
+    band: bool,
+    ttl: bool,
+    central: Central,
+    view: View,
+    host_show: HostShow,
+    resolved: Option<Resolved>, // lookup the newest samples were probed with
+    started: Instant,
+    last_sent: Option<Instant>, // when the most recent sample's probe went out
+    last_reply: Option<Instant>, // when the most recent reply arrived
+    input: Option<String>, // note being typed for a new chart marker
+    show_events: bool, // event log open over the chart
 }
 
 impl Ui {
     pub fn new(cfg: UiConfig) -> Self {
-        Self { state: UiState::new(cfg.history), cfg }
+        let mut state = UiState::new(cfg.history, cfg.backlog);
+        state.set_separate_unreachable(cfg.unreachable_as == UnreachableAs::Separate);
+        state.set_anonymizer(cfg.anon.clone());
+        state.set_warmup(cfg.warmup);
+        state.set_max_samples(cfg.max_samples);
+        if let Some(window) = cfg.prefs.window {
+            state.window = window.clamp(MIN_WINDOW.min(state.backlog), state.backlog);
+        }
+        if cfg.prefs.cumulative {
+            state.toggle_cumulative();
+        }
+        let p = cfg.prefs;
+        let resolved = *cfg.resolved.borrow();
+        Self {
+            state,
+            central: p.central.unwrap_or(cfg.central),
+            resolved,
+            cfg,
+            grid: p.grid,
+            jitter: p.jitter,
+            loss_line: p.loss_line,
+            bars: p.bars,
+            band: p.band,
+            ttl: p.ttl,
+            view: p.view,
+            host_show: p.host_show,
+            started: Instant::now(),
+            last_sent: None,
+            last_reply: None,
+            input: None,
+            show_events: false,
+        }
+    }
+
+    fn prefs(&self) -> Prefs {
+        Prefs {
+            grid: self.grid,
+            jitter: self.jitter,
+            loss_line: self.loss_line,
+            bars: self.bars,
+            band: self.band,
+            ttl: self.ttl,
+            window: Some(self.state.window()),
+            cumulative: self.state.is_cumulative(),
+            central: Some(self.central),
+            view: self.view,
+            host_show: self.host_show,
+        }
     }
 
     pub fn push(&mut self, s: &PingSample) {
-        self.state.push(s.rtt_ms, self.cfg.history);
+        // The pinger looks the host up before probing, so this sample used the newest lookup.
+        if self.cfg.resolved.has_changed().unwrap_or(false) {
+            let new = *self.cfg.resolved.borrow_and_update();
+            if let (Some(old), Some(new)) = (self.resolved, new) {
+                if old.addr != new.addr {
+                    let (old, new) = (self.cfg.anon.ip(old.addr), self.cfg.anon.ip(new.addr));
+                    self.state.log(s.ts, format!("address changed at #{}: {old} -> {new}", s.seq));
+                }
+            }
+            self.resolved = new;
+        }
+        self.state.push(s);
+        self.last_sent = Some(s.sent);
+        if s.rtt_ms.is_some() {
+            self.last_reply = Some(Instant::now());
+        }
     }
 
-    pub fn run_tui(mut self, mut rx: tokio::sync::mpsc::Receiver<PingSample>) -> anyhow::Result<()> {
-        enable_raw_mode()?;
-        let mut stdout = std::io::stdout();
-        execute!(stdout, EnterAlternateScreen, EnableMouseCapture)?;
-        let backend = CrosstermBackend::new(stdout);
-        let mut terminal = Terminal::new(backend)?;
-
-        let res = 'outer: loop {
-            while event::poll(std::time::Duration::from_millis(10))? {
-                if let Event::Key(k) = event::read()? {
-                    if k.code == KeyCode::Char('q')
-                        || k.code == KeyCode::Esc
-                        || (k.code == KeyCode::Char('c') && k.modifiers.contains(KeyModifiers::CONTROL))
-                    {
-                        break 'outer Ok(());
+    fn draw(&self, f: &mut Frame) {
+        let size = f.size();
+        if size.width < MIN_WIDTH || size.height < MIN_HEIGHT {
+            self.render_too_small(f);
+            return;
+        }
+        let footer = self.footer_lines(f.size().width.saturating_sub(2) as usize);
+        let chunks = Layout::default()
+            .direction(Direction::Vertical)
+            .constraints([
+                Constraint::Length(3),
+                Constraint::Percentage(100),
+                Constraint::Length(footer.len() as u16 + 2),
+            ].as_ref())
+            .split(f.size());
+
+        self.render_header(f, chunks[0]);
+
+        let mut main = chunks[1];
+        // The small panes would only repeat what a jitter or loss main view already shows.
+        if self.loss_line && self.view != View::Loss {
+            let parts = Layout::default()
+                .direction(Direction::Vertical)
+                .constraints([Constraint::Min(0), Constraint::Length(3)].as_ref())
+                .split(main);
+            main = parts[0];
+            self.render_loss_line(f, parts[1]);
+        }
+
+        if self.ttl {
+            let parts = Layout::default()
+                .direction(Direction::Vertical)
+                .constraints([Constraint::Min(0), Constraint::Length(6)].as_ref())
+                .split(main);
+            main = parts[0];
+            self.render_ttl(f, parts[1]);
+        }
+
+        if self.jitter && self.view != View::Jitter {
+            let panes = Layout::default()
+                .direction(Direction::Vertical)
+                .constraints([Constraint::Percentage(65), Constraint::Percentage(35)].as_ref())
+                .split(main);
+            self.render_latency(f, panes[0]);
+            self.render_jitter(f, panes[1]);
+        } else {
+            self.render_latency(f, main);
+        }
+
+        self.render_footer(f, chunks[2], footer);
+
+        if self.show_events {
+            self.render_events(f);
+        }
+        if let Some(text) = &self.input {
+            render_note_input(f, text);
+        }
+
+        self.cfg.color_depth.apply(f.buffer_mut());
+    }
+
+    /// Stands in for the whole layout when the terminal is too small for it, keeping
+    /// the latest result readable.
+    fn render_too_small(&self, f: &mut Frame) {
+        let size = f.size();
+        let last = match self.state.rtts.back() {
+            Some(e) => match e.rtt {
+                Some(rtt) => format!("#{} {rtt:.1} ms", e.seq),
+                None => format!("#{} lost", e.seq),
+            },
+            None => "waiting".into(),
+        };
+        let lines = vec![
+            Line::from(Span::styled("terminal too small", Style::default().fg(Color::Yellow))),
+            Line::from(format!("{}x{}, need {MIN_WIDTH}x{MIN_HEIGHT}", size.width, size.height)),
+            Line::from(last),
+        ];
+        let top = size.height.saturating_sub(lines.len() as u16) / 2;
+        let area = Rect { y: size.y + top, height: size.height - top, ..size };
+        f.render_widget(Paragraph::new(lines).alignment(Alignment::Center), area);
+    }
+
+    /// The header's "host:" field in the current `HostShow` form.
+    fn host_text(&self) -> String {
+        let name = match &self.cfg.label {
+            Some(label) => label.clone(),
+            None => self.cfg.anon.host(&self.cfg.host),
+        };
+        let addr = self.resolved.map(|r| self.cfg.anon.ip(r.addr));
+        match (self.host_show, addr) {
+            (HostShow::Name, _) | (HostShow::Addr, None) => format!("host: {name}"),
+            (HostShow::Addr, Some(addr)) => format!("host: {addr}"),
+            (HostShow::Both, addr) => {
+                let extra = match (addr, self.cfg.ptr.get()) {
+                    (Some(addr), Some(ptr)) => format!(" ({addr}, {ptr})"),
+                    (Some(addr), None) => format!(" ({addr})"),
+                    (None, Some(ptr)) => format!(" ({ptr})"),
+                    (None, None) => String::new(),
+                };
+                format!("host: {name}{extra}")
+            }
+        }
+    }
+
+    /// The event log, newest at the bottom, in a box over the chart; as many of the
+    /// kept events as fit.
+    fn render_events(&self, f: &mut Frame) {
+        let screen = f.size();
+        let area = Rect {
+            x: screen.x + 2,
+            y: screen.y + 3,
+            width: screen.width.saturating_sub(4),
+            height: screen.height.saturating_sub(6),
+        };
+        let rows = area.height.saturating_sub(2) as usize;
+        let events = &self.state.events;
+        let lines: Vec<Line> = events.iter().skip(events.len().saturating_sub(rows))
+            .map(|ev| {
+                let at = chrono::DateTime::<chrono::Local>::from(ev.ts).format("%H:%M:%S");
+                Line::from(vec![Span::styled(format!("{at} "), Style::default().fg(Color::Blue)), Span::raw(ev.msg.clone())])
+            })
+            .collect();
+        let title = format!(" Events ({} kept, e closes) ", events.len());
+        f.render_widget(Clear, area);
+        f.render_widget(Paragraph::new(lines).block(Block::default().borders(Borders::ALL).title(title)), area);
+    }
+
+    fn render_header(&self, f: &mut Frame, area: Rect) {
+        let mut title = vec![Span::raw(" Info ")];
+        if !self.cfg.warnings.is_empty() {
+            // Only the count fits here; the full text went to stderr at startup.
+            let n = self.cfg.warnings.len();
+            title.push(Span::styled(
+                format!("⚠ {n} warning{} (see stderr) ", if n == 1 { "" } else { "s" }),
+                Style::default().fg(Color::Yellow),
+            ));
+        }
+        let header = Paragraph::new(vec![
+            Line::from(vec![
+                Span::styled("rgping ", Style::default().fg(Color::Cyan).add_modifier(Modifier::BOLD)),
+                self.pulse(),
+                Span::raw(" "),
+                Span::raw(self.host_text()),
+                Span::raw(self.cfg.source.map(|ip| format!("   from: {}", self.cfg.anon.ip(ip))).unwrap_or_default()),
+                Span::raw(format!("   elapsed: {}", format_elapsed(self.started.elapsed()))),
+                Span::raw(format!("   sent: {}", self.state.total())),
+                Span::raw(format!("   kept: {}", self.state.backlog())),
+                Span::raw(format!("   time: {}", chrono::Local::now().format("%H:%M:%S"))),
+                Span::raw(self.countdown()),
+            ]),
+        ]).block(Block::default().borders(Borders::ALL).title(Line::from(title)));
+        f.render_widget(header, area);
+    }
+
+    /// A dot that lights up for `PULSE` after each reply, so slow intervals still show
+    /// that data is coming in.
+    fn pulse(&self) -> Span<'static> {
+        match self.last_reply {
+            Some(at) if at.elapsed() < PULSE => Span::styled("●", Style::default().fg(Color::Green).add_modifier(Modifier::BOLD)),
+            _ => Span::styled("●", Style::default().fg(Color::DarkGray)),
+        }
+    }
+
+    /// Whether the reply dot is lit and needs another frame to go dark on time.
+    fn pulsing(&self) -> bool {
+        self.last_reply.is_some_and(|at| at.elapsed() < PULSE + FRAME)
+    }
+
+    fn countdown(&self) -> String {
+        if !self.cfg.countdown {
+            return String::new();
+        }
+        if *self.cfg.pause.borrow() {
+            return "   next: paused".into();
+        }
+        let Some(sent) = self.last_sent else { return "   next: waiting".into() };
+        match (sent + self.cfg.interval).checked_duration_since(Instant::now()) {
+            Some(left) => format!("   next in {:.1}s", left.as_secs_f64()),
+            // The next probe is already out (or the previous one is still running).
+            None => "   next: probing".into(),
+        }
+    }
+
+    fn render_latency(&self, f: &mut Frame, area: Rect) {
+        match self.view {
+            View::Rtt => {}
+            View::Jitter => return self.render_jitter(f, area),
+            View::Loss => return self.render_loss_chart(f, area),
+        }
+        if self.bars {
+            return self.render_bars(f, area);
+        }
+        let visible: Vec<Entry> = self.state.visible().copied().collect();
+        let shown = median_filter(&visible.iter().map(|e| e.rtt).collect::<Vec<_>>(), self.cfg.median_filter);
+
+        // Each sample sits at its index, or at its offset in seconds on a time axis.
+        let xs: Vec<f64> = match visible.first() {
+            Some(first) if self.cfg.time_axis => visible.iter()
+                .map(|e| e.sent.saturating_duration_since(first.sent).as_secs_f64())
+                .collect(),
+            _ => (0..visible.len()).map(|i| i as f64).collect(),
+        };
+        let columns = area.width.saturating_sub(2) as usize;
+        let points_where = |loaded: bool| -> Vec<(f64, f64)> {
+            let points: Vec<(f64, f64)> = visible.iter()
+                .zip(&shown)
+                .zip(&xs)
+                .filter(|((e, _), _)| e.loaded == loaded)
+                .filter_map(|((_, rtt), &x)| rtt.map(|ms| (x, ms)))
+                .collect();
+            downsample(points, columns)
+        };
+        let points = points_where(false);
+        let loaded_points = if self.cfg.load { points_where(true) } else { Vec::new() };
+
+        // Band of ±1 stddev around the rolling mean of the replies before each sample,
+        // drawn as a zigzag between its edges, plus the replies that fall outside it.
+        let (mut band_fill, mut band_mean, mut anomalies) = (Vec::new(), Vec::new(), Vec::new());
+        if self.band {
+            let rtts: Vec<Option<f64>> = visible.iter().map(|e| e.rtt).collect();
+            for ((band, rtt), &x) in rolling_band(&rtts, BAND_WINDOW).into_iter().zip(&rtts).zip(&xs) {
+                let Some((mean, sd)) = band else { continue };
+                band_fill.push((x, mean - sd));
+                band_fill.push((x, mean + sd));
+                band_mean.push((x, mean));
+                if let Some(ms) = rtt.filter(|ms| (ms - mean).abs() > sd) {
+                    anomalies.push((x, ms));
+                }
+            }
+            band_mean = downsample(band_mean, columns);
+        }
+
+        let y_max = self.state.y_max(self.cfg.y_headroom);
+        let window = self.state.window();
+        let x_max = if self.cfg.time_axis {
+            xs.last().copied().unwrap_or_default().max(1.0)
+        } else {
+            window as f64
+        };
+        let (x_title, x_labels) = if self.cfg.time_axis {
+            ("Seconds", vec![
+                Span::raw("0"),
+                Span::raw(format!("{:.1}", x_max / 2.0)),
+                Span::raw(format!("{x_max:.1}")),
+            ])
+        } else {
+            ("Samples", vec![
+                Span::raw("0"),
+                Span::raw(format!("{}", window / 2)),
+                Span::raw(format!("{}", window)),
+            ])
+        };
+
+        let ticks = y_ticks(y_max, self.cfg.y_ticks);
+
+        // Horizontal lines at the non-zero y-axis label positions.
+        let grid_lines: Vec<[(f64, f64); 2]> = if self.grid {
+            ticks.iter().filter(|&&y| y > 0.0).map(|&y| [(0.0, y), (x_max, y)]).collect()
+        } else {
+            Vec::new()
+        };
+
+        // Gridlines go first so the RTT line is drawn on top of them.
+        let mut datasets: Vec<Dataset> = grid_lines.iter()
+            .map(|line| Dataset::default()
+                .graph_type(GraphType::Line)
+                .style(Style::default().fg(GRID_COLOR))
+                .data(line))
+            .collect();
+
+        // A vertical line where the wall clock jumped, since the samples either side of it
+        // are further apart (or closer) in wall time than the chart suggests.
+        let clock_marks: Vec<[(f64, f64); 2]> = visible.iter()
+            .zip(&xs)
+            .filter(|(e, _)| e.clock_jump)
+            .map(|(_, &x)| [(x, 0.0), (x, y_max)])
+            .collect();
+        datasets.extend(clock_marks.iter().map(|line| Dataset::default()
+            .graph_type(GraphType::Line)
+            .style(Style::default().fg(Color::White))
+            .data(line)));
+
+        // A vertical line at each note, labelled in the legend.
+        let note_marks: Vec<([(f64, f64); 2], String)> = self.state.notes.iter()
+            .filter_map(|n| {
+                let x = visible.iter().zip(&xs).find(|(e, _)| e.seq == n.seq)?.1;
+                Some(([(*x, 0.0), (*x, y_max)], format!("#{} {}", n.seq, n.text)))
+            })
+            .collect();
+        datasets.extend(note_marks.iter().map(|(line, name)| Dataset::default()
+            .name(name.clone())
+            .graph_type(GraphType::Line)
+            .style(Style::default().fg(Color::LightCyan))
+            .data(line)));
+
+        // A vertical line where each confirmed TTL change starts.
+        let ttl_marks: Vec<[(f64, f64); 2]> = visible.iter()
+            .zip(&xs)
+            .filter(|(e, _)| e.ttl_change)
+            .map(|(_, &x)| [(x, 0.0), (x, y_max)])
+            .collect();
+        datasets.extend(ttl_marks.iter().map(|line| Dataset::default()
+            .graph_type(GraphType::Line)
+            .style(Style::default().fg(Color::Blue))
+            .data(line)));
+
+        if self.band {
+            datasets.push(Dataset::default()
+                .marker(Marker::Braille)
+                .graph_type(GraphType::Line)
+                .style(Style::default().fg(BAND_COLOR))
+                .data(&band_fill));
+            datasets.push(Dataset::default()
+                .name("mean ±1σ")
+                .marker(Marker::Braille)
+                .graph_type(GraphType::Line)
+                .style(Style::default().fg(Color::Cyan))
+                .data(&band_mean));
+        }
+
+        datasets.push(Dataset::default()
+            .name(if self.cfg.load { "idle (ms)" } else { "RTT (ms)" })
+            .marker(self.cfg.marker)
+            .graph_type(GraphType::Line)
+            .style(Style::default().fg(Color::Green))
+            .data(&points));
+        if self.cfg.load {
+            datasets.push(Dataset::default()
+                .name("loaded (ms)")
+                .marker(self.cfg.marker)
+                .graph_type(GraphType::Line)
+                .style(Style::default().fg(Color::Magenta))
+                .data(&loaded_points));
+        }
+        // Warm-up replies are drawn over in grey, since the stats leave them out.
+        let warmup: Vec<(f64, f64)> = visible.iter()
+            .zip(&shown)
+            .zip(&xs)
+            .filter(|((e, _), _)| e.warmup)
+            .filter_map(|((_, rtt), &x)| rtt.map(|ms| (x, ms)))
+            .collect();
+        if !warmup.is_empty() {
+            datasets.push(Dataset::default()
+                .name("warm-up")
+                .marker(Marker::Dot)
+                .graph_type(GraphType::Scatter)
+                .style(Style::default().fg(Color::DarkGray))
+                .data(&warmup));
+        }
+        // Retried, duplicated or reordered replies get a marker over the line.
+        let flagged: Vec<(f64, f64)> = visible.iter()
+            .zip(&shown)
+            .zip(&xs)
+            .filter(|((e, _), _)| !e.flags.is_empty())
+            .filter_map(|((_, rtt), &x)| rtt.map(|ms| (x, ms)))
+            .collect();
+        datasets.push(Dataset::default()
+            .marker(Marker::Dot)
+            .graph_type(GraphType::Scatter)
+            .style(Style::default().fg(Color::Yellow))
+            .data(&flagged));
+        if self.band {
+            datasets.push(Dataset::default()
+                .marker(Marker::Dot)
+                .graph_type(GraphType::Scatter)
+                .style(Style::default().fg(Color::Red).add_modifier(Modifier::BOLD))
+                .data(&anomalies));
+        }
+
+        let title = if self.state.is_live() {
+            format!(" {} ", self.cfg.title)
+        } else {
+            let (first, last) = self.state.visible_seq();
+            format!(" {}  #{first}-#{last}  (paused, End: live) ", self.cfg.title)
+        };
+
+        let chart = Chart::new(datasets)
+            .block(Block::default().borders(Borders::ALL).title(title))
+            .x_axis(
+                Axis::default()
+                    .title(x_title)
+                    .bounds([0.0, x_max])
+                    .labels(x_labels)
+            )
+            .y_axis(
+                Axis::default()
+                    .title("RTT (ms)")
+                    .bounds([0.0, y_max])
+                    .labels(tick_labels(&ticks))
+            );
+
+        f.render_widget(chart, area);
+    }
+
+    fn severity(&self, rtt: f64) -> Severity {
+        if rtt >= self.cfg.crit_ms {
+            Severity::Crit
+        } else if rtt >= self.cfg.warn_ms {
+            Severity::Warn
+        } else {
+            Severity::Ok
+        }
+    }
+
+    /// Explains the `severity` cutoffs, e.g. `■ <100  ■ <250  ■ ≥250 ms  ■ lost`.
+    fn threshold_legend(&self) -> Vec<Span<'static>> {
+        let (warn, crit) = (self.cfg.warn_ms, self.cfg.crit_ms);
+        let palette = self.cfg.palette;
+        let key = |s: Severity| Span::styled(
+            format!("{} ", palette.glyph(s).unwrap_or("■")),
+            Style::default().fg(palette.color(s)),
+        );
+        vec![
+            key(Severity::Ok), Span::raw(format!("<{warn:.0}  ")),
+            key(Severity::Warn), Span::raw(format!("<{crit:.0}  ")),
+            key(Severity::Crit), Span::raw(format!("≥{crit:.0} ms  ")),
+            Span::styled("■", self.lost_style()), Span::raw(" lost "),
+        ]
+    }
+
+    fn lost_style(&self) -> Style {
+        Style::default().fg(self.cfg.palette.color(Severity::Crit)).add_modifier(Modifier::REVERSED)
+    }
+
+    fn loss_severity(&self, pct: f64) -> Severity {
+        if pct > self.cfg.loss_crit {
+            Severity::Crit
+        } else if pct > self.cfg.loss_warn {
+            Severity::Warn
+        } else {
+            Severity::Ok
+        }
+    }
+
+    /// One bar per recent sample, coloured by `--warn`/`--crit`; losses are short red stubs.
+    fn render_bars(&self, f: &mut Frame, area: Rect) {
+        let width = area.width.saturating_sub(2) as usize;
+        let visible: Vec<Entry> = self.state.visible().copied().collect();
+        let recent = &visible[visible.len().saturating_sub(width)..];
+
+        // Bar values are in tenths of a millisecond to keep some vertical resolution.
+        let y_max = self.state.y_max(self.cfg.y_headroom);
+        let max = (y_max * 10.0) as u64;
+        let stub = (max / 20).max(1);
+        let bars: Vec<Bar> = recent.iter()
+            .map(|e| match e.rtt {
+                Some(ms) => {
+                    let severity = self.severity(ms);
+                    let color = self.cfg.palette.color(severity);
+                    // Shapes on the degraded bars only; a glyph on every bar would just be noise.
+                    let glyph = self.cfg.palette.glyph(severity).filter(|_| severity != Severity::Ok);
+                    Bar::default()
+                        .value((ms * 10.0).round() as u64)
+                        .style(Style::default().fg(color))
+                        .value_style(Style::default().fg(Color::Black).bg(color))
+                        .text_value(glyph.unwrap_or_default().to_string())
+                }
+                None => Bar::default()
+                    .value(stub)
+                    .style(self.lost_style())
+                    .text_value(String::new()),
+            })
+            .collect();
+
+        let mut title = vec![Span::raw(format!(" {}  (bars, max {y_max:.0} ms)  ", self.cfg.title))];
+        title.extend(self.threshold_legend());
+        let chart = BarChart::default()
+            .block(Block::default().borders(Borders::ALL).title(Line::from(title)))
+            .bar_width(1)
+            .bar_gap(0)
+            .max(max)
+            .data(BarGroup::default().bars(&bars));
+        f.render_widget(chart, area);
+    }
+
+    fn render_jitter(&self, f: &mut Frame, area: Rect) {
+        let rtts: Vec<Option<f64>> = self.state.visible().map(|e| e.rtt).collect();
+        let points = jitter_series(&rtts);
+
+        let y_max = (points.iter().map(|&(_, j)| j).fold(1.0, f64::max) * 1.20).ceil();
+        let window = self.state.window();
+        let x_max = window as f64;
+
+        let dataset = Dataset::default()
+            .name("jitter (ms)")
+            .marker(self.cfg.marker)
+            .graph_type(GraphType::Line)
+            .style(Style::default().fg(Color::Cyan))
+            .data(&points);
+
+        let chart = Chart::new(vec![dataset])
+            .block(Block::default().borders(Borders::ALL).title(" Jitter "))
+            .x_axis(
+                Axis::default()
+                    .bounds([0.0, x_max])
+                    .labels(vec![
+                        Span::raw("0"),
+                        Span::raw(format!("{}", window / 2)),
+                        Span::raw(format!("{}", window)),
+                    ])
+            )
+            .y_axis(
+                Axis::default()
+                    .title("Δ (ms)")
+                    .bounds([0.0, y_max])
+                    .labels(tick_labels(&y_ticks(y_max, self.cfg.y_ticks)))
+            );
+
+        f.render_widget(chart, area);
+    }
+
+    /// The reply TTL over the window as a step line; a changing trace means the route
+    /// back from the target changed.
+    fn render_ttl(&self, f: &mut Frame, area: Rect) {
+        let points: Vec<(f64, f64)> = self.state.visible()
+            .enumerate()
+            .filter_map(|(i, e)| e.ttl.map(|ttl| (i as f64, ttl as f64)))
+            .collect();
+        let (lo, hi) = points.iter().fold((u8::MAX as f64, 0.0f64), |(lo, hi), &(_, t)| (lo.min(t), hi.max(t)));
+        // A little room either side so a steady TTL isn't drawn on the pane's edge.
+        let (lo, hi) = if points.is_empty() { (0.0, 255.0) } else { ((lo - 2.0).max(0.0), hi + 2.0) };
+        let window = self.state.window();
+
+        let dataset = Dataset::default()
+            .name("ttl")
+            .marker(self.cfg.marker)
+            .graph_type(GraphType::Line)
+            .style(Style::default().fg(Color::Magenta))
+            .data(&points);
+
+        let chart = Chart::new(vec![dataset])
+            .block(Block::default().borders(Borders::ALL).title(" TTL "))
+            .x_axis(Axis::default().bounds([0.0, window as f64]))
+            .y_axis(
+                Axis::default()
+                    .bounds([lo, hi])
+                    .labels(vec![Span::raw(format!("{lo:.0}")), Span::raw(format!("{hi:.0}"))])
+            );
+
+        f.render_widget(chart, area);
+    }
+
+    /// The rolling loss rate as a full-size chart, for the loss main view.
+    fn render_loss_chart(&self, f: &mut Frame, area: Rect) {
+        let rtts: Vec<Option<f64>> = self.state.visible().map(|e| e.rtt).collect();
+        let points: Vec<(f64, f64)> = rolling_loss(&rtts, LOSS_SUBWINDOW)
+            .into_iter()
+            .enumerate()
+            .map(|(i, pct)| (i as f64, pct as f64))
+            .collect();
+        let window = self.state.window();
+
+        let dataset = Dataset::default()
+            .name("loss (%)")
+            .marker(self.cfg.marker)
+            .graph_type(GraphType::Line)
+            .style(Style::default().fg(Color::Red))
+            .data(&points);
+
+        let chart = Chart::new(vec![dataset])
+            .block(Block::default().borders(Borders::ALL).title(format!(" Loss % (per {LOSS_SUBWINDOW}) ")))
+            .x_axis(
+                Axis::default()
+                    .bounds([0.0, window as f64])
+                    .labels(vec![
+                        Span::raw("0"),
+                        Span::raw(format!("{}", window / 2)),
+                        Span::raw(format!("{}", window)),
+                    ])
+            )
+            .y_axis(
+                Axis::default()
+                    .title("loss (%)")
+                    .bounds([0.0, 100.0])
+                    .labels(tick_labels(&y_ticks(100.0, self.cfg.y_ticks)))
+            );
+
+        f.render_widget(chart, area);
+    }
+
+    fn render_loss_line(&self, f: &mut Frame, area: Rect) {
+        let rtts: Vec<Option<f64>> = self.state.visible().map(|e| e.rtt).collect();
+        let rates = rolling_loss(&rtts, LOSS_SUBWINDOW);
+        // One bar per column; keep the newest values when the window is wider than the pane.
+        let width = area.width.saturating_sub(2) as usize;
+        let data = &rates[rates.len().saturating_sub(width)..];
+
+        let spark = Sparkline::default()
+            .block(Block::default().borders(Borders::ALL).title(format!(" Loss % (per {LOSS_SUBWINDOW}) ")))
+            .style(Style::default().fg(Color::Red))
+            .max(100)
+            .data(data);
+        f.render_widget(spark, area);
+    }
+
+    /// Footer text for an inner width of `width` columns: stats and key hints on one line
+    /// if they fit, otherwise the hints below the stats, wrapped onto as many lines as
+    /// `HINT_LINES` allows. Past that the keys alone are listed, and once the stats
+    /// don't fit either they use short labels.
+    fn footer_lines(&self, width: usize) -> Vec<Line<'static>> {
+        let full = self.footer_stats(false);
+        let hints = HINTS.join("   ");
+        if full.width() + 3 + hints.chars().count() <= width {
+            let mut spans = full.spans;
+            spans.push(Span::raw(format!("   {hints}")));
+            return vec![Line::from(spans)];
+        }
+        let long = wrap_items(HINTS, "   ", width);
+        let (stats, hints) = if full.width() > width {
+            (self.footer_stats(true), wrap_items(SHORT_HINTS, "  ", width))
+        } else if long.len() <= HINT_LINES {
+            (full, long)
+        } else {
+            (full, wrap_items(SHORT_HINTS, "  ", width))
+        };
+        std::iter::once(stats).chain(hints.into_iter().map(Line::from)).collect()
+    }
+
+    fn footer_stats(&self, compact: bool) -> Line<'static> {
+        // Full and short forms of each label, e.g. `   loss: ` and ` loss:`.
+        let label = |full: &str, short: &str| if compact { format!(" {short}:") } else { format!("   {full}: ") };
+
+        let last = match self.state.rtts.back() {
+            Some(Entry { outcome: Outcome::Unreachable(e), .. }) if compact => e.reason.to_string(),
+            Some(Entry { outcome: Outcome::Unreachable(e), .. }) => self.cfg.anon.icmp(e),
+            _ => self.state.last_avg(self.cfg.last_avg).map(|v| format!("{v:.1} ms")).unwrap_or_else(|| "timeout".into()),
+        };
+        let last_label = match (self.cfg.last_avg, compact) {
+            (_, true) => "L:".to_string(),
+            (0 | 1, false) => "last: ".to_string(),
+            (n, false) => format!("last {n}: "),
+        };
+        let ci = match self.central {
+            Central::Arith => self.state.mean_ci().map(|h| format!(" ±{h:.1}")).unwrap_or_default(),
+            _ => String::new(),
+        };
+        let avg  = self.state.central(self.central).map(|v| format!("{v:.1}{ci} ms")).unwrap_or_else(|| "-".into());
+        let loss_pct = self.state.loss_pct();
+        let mut loss = match self.state.loss_pattern() {
+            Some(pattern) => format!("{loss_pct:.1}% ({pattern})"),
+            None => format!("{loss_pct:.1}%"),
+        };
+        let loss_severity = self.loss_severity(loss_pct);
+        if let Some(glyph) = self.cfg.palette.glyph(loss_severity).filter(|_| loss_severity != Severity::Ok) {
+            loss = format!("{loss} {glyph}");
+        }
+        let loss_style = Style::default().fg(self.cfg.palette.color(loss_severity));
+
+        // Median and p95 since start are over what's kept, not the whole run.
+        let central_label = match (self.state.is_cumulative() && self.central.needs_samples(), compact) {
+            (true, false) => format!("   {} of kept: ", self.central.label()),
+            (true, true) => format!(" {}(kept):", self.central.short_label()),
+            (false, _) => label(self.central.label(), self.central.short_label()),
+        };
+        let scope = match (self.state.is_cumulative(), compact) {
+            (true, false) => "since start  ",
+            (false, false) => "window  ",
+            (true, true) => "S ",
+            (false, true) => "W ",
+        };
+        let mut spans = Vec::new();
+        // Not to be confused with scrolling back, which only stops the chart moving.
+        if *self.cfg.pause.borrow() {
+            let text = if compact { "PAUSED" } else { "probing paused (p resumes)" };
+            spans.push(Span::styled(text, Style::default().fg(Color::Black).bg(Color::Yellow)));
+            spans.push(Span::raw(" "));
+        }
+        spans.extend([
+            Span::styled(scope, Style::default().fg(Color::Cyan)),
+            Span::raw(last_label), Span::styled(last, Style::default().fg(Color::Green)),
+            Span::raw(central_label), Span::styled(avg, Style::default().fg(Color::Yellow)),
+            Span::raw(label("loss", "loss")), Span::styled(loss, loss_style),
+        ]);
+        if let Some(pps) = self.state.rate() {
+            spans.push(Span::raw(format!("{}{pps:.1}/s", label("rate", "rate"))));
+        }
+        // Probes overrunning the interval (a slow ping, a busy machine) show up as positive drift.
+        if let Some(gap) = self.state.mean_gap() {
+            let interval = self.cfg.interval.as_secs_f64();
+            let drift = (gap.as_secs_f64() - interval) / interval * 100.0;
+            let color = if drift.abs() > DRIFT_WARN_PCT { Color::Yellow } else { Color::Reset };
+            spans.push(Span::raw(label("gap", "gap")));
+            spans.push(Span::styled(
+                format!("{:.0}ms ({drift:+.0}%)", gap.as_secs_f64() * 1000.0),
+                Style::default().fg(color),
+            ));
+        }
+        if !self.cfg.literal {
+            let dns = self.resolved
+                .map(|r| format!("{:.1}ms", r.took.as_secs_f64() * 1000.0))
+                .unwrap_or_else(|| "failed".into());
+            spans.push(Span::raw(format!("{}{dns}", label("dns", "dns"))));
+        }
+        if self.cfg.unreachable_as == UnreachableAs::Separate {
+            spans.push(Span::raw(format!("{}{}", label("unreach", "unr"), self.state.unreachable)));
+        }
+        if self.cfg.warmup > 0 {
+            let warmed = self.state.warmed();
+            let text = if warmed < self.cfg.warmup { format!("{warmed}/{}", self.cfg.warmup) } else { format!("{warmed} excluded") };
+            spans.push(Span::styled(format!("{}{text}", label("warm-up", "wu")), Style::default().fg(Color::DarkGray)));
+        }
+        if self.cfg.retries {
+            spans.push(Span::raw(format!("{}{}", label("retried", "retr"), self.state.retried)));
+        }
+        if self.cfg.load {
+            let idle = self.state.avg_loaded(false);
+            let loaded = self.state.avg_loaded(true);
+            let fmt = |v: Option<f64>| v.map(|v| format!("{v:.1} ms")).unwrap_or_else(|| "-".into());
+            spans.push(Span::raw(label("idle", "I")));
+            spans.push(Span::styled(fmt(idle), Style::default().fg(Color::Green)));
+            spans.push(Span::raw(label("loaded", "LD")));
+            spans.push(Span::styled(fmt(loaded), Style::default().fg(Color::Magenta)));
+            if let (Some(idle), Some(loaded)) = (idle, loaded) {
+                let delta = loaded - idle;
+                spans.push(Span::raw(format!(" ({delta:+.1} ms)")));
+                if delta >= BLOAT_MS {
+                    spans.push(Span::styled(" bufferbloat!", Style::default().fg(Color::Red).add_modifier(Modifier::BOLD)));
+                }
+            }
+        }
+        let dropped = self.cfg.channel.dropped();
+        if dropped > 0 {
+            spans.push(Span::raw(label("skipped", "skip")));
+            spans.push(Span::styled(dropped.to_string(), Style::default().fg(Color::Yellow)));
+        }
+        if let Some(ttl) = self.state.ttl {
+            spans.push(Span::raw(format!("{}{ttl}", label("ttl", "ttl"))));
+        }
+        if let Some(ev) = self.state.events.back() {
+            let at = chrono::DateTime::<chrono::Local>::from(ev.ts).format("%H:%M:%S");
+            spans.push(Span::raw(if compact { " " } else { "   " }));
+            spans.push(Span::styled(format!("{at} {}", ev.msg), Style::default().fg(Color::Blue)));
+        }
+        if let Some(err) = &self.state.error {
+            spans.push(Span::raw(label("error", "err")));
+            spans.push(Span::styled(err.to_string(), Style::default().fg(Color::Red).add_modifier(Modifier::BOLD)));
+        }
+        Line::from(spans)
+    }
+
+    fn render_footer(&self, f: &mut Frame, area: Rect, lines: Vec<Line<'static>>) {
+        let foot = Paragraph::new(lines).block(Block::default().borders(Borders::ALL));
+        f.render_widget(foot, area);
+    }
+
+    /// Adds a marker note at the newest sample.
+    fn add_note(&mut self, text: String) {
+        let note = Note { seq: self.state.last_seq, ts: SystemTime::now(), text };
+        self.cfg.summary.lock().unwrap().add_note(note.clone());
+        self.state.add_note(note);
+    }
+
+    /// Handles one key press; returns true when it asks to quit.
+    fn handle_key(&mut self, k: KeyEvent) -> bool {
+        let ctrl_c = k.code == KeyCode::Char('c') && k.modifiers.contains(KeyModifiers::CONTROL);
+        // While a note is being typed every other key is part of it.
+        if let Some(text) = self.input.as_mut().filter(|_| !ctrl_c) {
+            match k.code {
+                KeyCode::Enter => {
+                    let text = text.trim().to_string();
+                    self.input = None;
+                    if !text.is_empty() {
+                        self.add_note(text);
                     }
                 }
+                KeyCode::Esc => self.input = None,
+                KeyCode::Backspace => {
+                    text.pop();
+                }
+                KeyCode::Char(c) if text.chars().count() < NOTE_MAX => text.push(c),
+                _ => {}
             }
+            return false;
+        }
+        if k.code == KeyCode::Char('q')
+            || k.code == KeyCode::Esc
+            || (k.code == KeyCode::Char('c') && k.modifiers.contains(KeyModifiers::CONTROL))
+        {
+            return true;
+        }
+        let step = (self.state.window() / 10).max(1);
+        match k.code {
+            KeyCode::Left => self.state.scroll_back(step),
+            KeyCode::Right => self.state.scroll_forward(step),
+            KeyCode::End => self.state.follow(),
+            KeyCode::Char('+') | KeyCode::Char(']') => self.state.zoom_in(),
+            KeyCode::Char('-') | KeyCode::Char('[') => self.state.zoom_out(),
+            KeyCode::Char('g') => self.grid = !self.grid,
+            KeyCode::Char('j') => self.jitter = !self.jitter,
+            KeyCode::Char('l') => self.loss_line = !self.loss_line,
+            KeyCode::Char('b') => self.bars = !self.bars,
+            KeyCode::Char('s') => self.band = !self.band,
+            KeyCode::Char('t') => self.ttl = !self.ttl,
+            KeyCode::Char('c') => self.central = self.central.next(),
+            KeyCode::Char('v') => self.view = self.view.next(),
+            KeyCode::Char('n') => self.host_show = self.host_show.next(),
+            KeyCode::Char('m') => self.input = Some(String::new()),
+            KeyCode::Char('e') => self.show_events = !self.show_events,
+            KeyCode::Char('p') => self.cfg.pause.send_modify(|paused| *paused = !*paused),
+            KeyCode::Char('w') => self.state.toggle_cumulative(),
+            KeyCode::Char('d') => self.state.dump_visible(&self.cfg.anon.host(&self.cfg.host)),
+            KeyCode::Char('H') | KeyCode::PageUp => self.state.resize_backlog(self.state.backlog().saturating_mul(2)),
+            KeyCode::Char('h') | KeyCode::PageDown => self.state.resize_backlog(self.state.backlog() / 2),
+            _ => {}
+        }
+        false
+    }
+
+    /// Waits on input and samples together: a key is handled and drawn as soon as it
+    /// arrives, while samples only mark the frame dirty and are drawn at most once per
+    /// `FRAME` however fast they come in. Between samples it redraws every `idle`,
+    /// whatever the probe interval.
+    async fn event_loop<B: Backend>(
+        &mut self,
+        terminal: &mut Terminal<B>,
+        mut rx: tokio::sync::mpsc::Receiver<PingSample>,
+    ) -> anyhow::Result<()> {
+        let mut input = EventStream::new();
+        let mut frames = tokio::time::interval(FRAME);
+        frames.set_missed_tick_behavior(MissedTickBehavior::Skip);
+        // The header clocks (elapsed, countdown) move even when no samples do.
+        let idle = match self.cfg.ui_fps {
+            Some(fps) => Duration::from_secs_f64(1.0 / fps),
+            None if self.cfg.countdown => Duration::from_millis(100),
+            None => Duration::from_secs(1),
+        };
+        let mut open = true;
+        let mut dirty = true;
+        let mut drawn = Instant::now();
 
-            while let Ok(s) = rx.try_recv() {
-                self.push(&s);
+        loop {
+            tokio::select! {
+                ev = input.next() => match ev {
+                    Some(Ok(Event::Key(k))) => {
+                        if self.handle_key(k) {
+                            return Ok(());
+                        }
+                        terminal.draw(|f| self.draw(f))?;
+                        dirty = false;
+                        drawn = Instant::now();
+                    }
+                    // Redraw at the new size straight away rather than on the next frame
+                    // tick; draw() resizes and clears the buffers first.
+                    Some(Ok(Event::Resize(..))) => {
+                        terminal.draw(|f| self.draw(f))?;
+                        dirty = false;
+                        drawn = Instant::now();
+                    }
+                    Some(Ok(_)) => dirty = true,
+                    Some(Err(e)) => return Err(e.into()),
+                    None => return Ok(()),
+                },
+                s = rx.recv(), if open => match s {
+                    Some(s) => {
+                        self.push(&s);
+                        dirty = true;
+                    }
+                    // The pinger is done (--count); keep showing the result until quit.
+                    None => open = false,
+                },
+                _ = frames.tick() => {
+                    if self.cfg.stop.load(Ordering::Relaxed) {
+                        return Ok(());
+                    }
+                    if dirty || drawn.elapsed() >= idle || self.pulsing() {
+                        terminal.draw(|f| self.draw(f))?;
+                        dirty = false;
+                        drawn = Instant::now();
+                    }
+                }
+            }
+        }
+    }
+
+    pub async fn run_tui(mut self, rx: tokio::sync::mpsc::Receiver<PingSample>) -> anyhow::Result<()> {
+        let altscreen = self.cfg.altscreen;
+        enable_raw_mode()?;
+        let mut stdout = std::io::stdout();
+        if altscreen {
+            execute!(stdout, EnterAlternateScreen)?;
+        }
+        execute!(stdout, EnableMouseCapture)?;
+
+        // Put the terminal back before the panic message prints, or it lands in the
+        // alternate screen and the shell is left in raw mode.
+        let default_hook = std::panic::take_hook();
+        std::panic::set_hook(Box::new(move |info| {
+            let _ = restore_terminal(altscreen);
+            default_hook(info);
+        }));
+
+        let backend = CrosstermBackend::new(stdout);
+        let terminal = if altscreen {
+            Terminal::new(backend)
+        } else {
+            // An inline viewport asks the terminal where the cursor is, which can fail.
+            crossterm::terminal::size().and_then(|(_, rows)| {
+                Terminal::with_options(backend, TerminalOptions { viewport: Viewport::Inline(rows) })
+            })
+        };
+        let mut terminal = match terminal {
+            Ok(t) => t,
+            Err(e) => {
+                let _ = std::panic::take_hook();
+                let _ = restore_terminal(altscreen);
+                return Err(e.into());
             }
+        };
 
-            terminal.draw(|f| {
-                let chunks = Layout::default()
-                    .direction(Direction::Vertical)
-                    .constraints([
-                        Constraint::Length(3),
-                        Constraint::Percentage(100),
-                        Constraint::Length(2),
-                    ].as_ref())
-                    .split(f.size());
-
-                let header = Paragraph::new(vec![
-                    Line::from(vec![
-                        Span::styled("rgping  ", Style::default().fg(Color::Cyan).add_modifier(Modifier::BOLD)),
-                        Span::raw(format!("host: {}", self.cfg.host)),
-                    ]),
-                ]).block(Block::default().borders(Borders::ALL).title(" Info "));
-                f.render_widget(header, chunks[0]);
-
-                let points: Vec<(f64, f64)> = self.state.rtts.iter()
-                    .enumerate()
-                    .filter_map(|(i, v)| v.map(|ms| (i as f64, ms)))
-                    .collect();
-
-                let y_max = self.state.y_max();
-                let x_max = self.cfg.history as f64;
-
-                let dataset = Dataset::default()
-                    .name("RTT (ms)")
-                    .graph_type(GraphType::Line)
-                    .style(Style::default().fg(Color::Green))
-                    .data(&points);
-
-                let chart = Chart::new(vec![dataset])
-                    .block(Block::default().borders(Borders::ALL).title(" Latency "))
-                    .x_axis(
-                        Axis::default()
-                            .title("Samples")
-                            .bounds([0.0, x_max])
-                            .labels(vec![
-                                Span::raw("0"),
-                                Span::raw(format!("{}", self.cfg.history / 2)),
-                                Span::raw(format!("{}", self.cfg.history)),
-                            ])
-                    )
-                    .y_axis(
-                        Axis::default()
-                            .title("RTT (ms)")
-                            .bounds([0.0, y_max])
-                            .labels(vec![
-                                Span::raw("0"),
-                                Span::raw(format!("{:.0}", y_max / 2.0)),
-                                Span::raw(format!("{:.0}", y_max)),
-                            ])
-                    );
-
-                f.render_widget(chart, chunks[1]);
-
-                let last = self.state.last.map(|v| format!("{v:.1} ms")).unwrap_or_else(|| "timeout".into());
-                let avg  = self.state.avg().map(|v| format!("{v:.1} ms")).unwrap_or_else(|| "-".into());
-                let loss = format!("{:.1}%", self.state.loss_pct());
-
-                let foot = Paragraph::new(Line::from(vec![
-                    Span::raw("last: "), Span::styled(last, Style::default().fg(Color::Green)),
-                    Span::raw("   avg: "),  Span::styled(avg,  Style::default().fg(Color::Yellow)),
-                    Span::raw("   loss: "), Span::styled(loss, Style::default().fg(Color::Red)),
-                    Span::raw("   quit: q / Esc / Ctrl-C"),
-                ])).block(Block::default().borders(Borders::ALL));
-                f.render_widget(foot, chunks[2]);
-            })?;
-        };
-
-        disable_raw_mode()?;
-        execute!(terminal.backend_mut(), LeaveAlternateScreen, DisableMouseCapture)?;
-        terminal.show_cursor()?;
+        let res = self.event_loop(&mut terminal, rx).await;
+
+        // Back to the default hook now that the terminal is about to be restored.
+        let _ = std::panic::take_hook();
+        restore_terminal(altscreen)?;
+        if !altscreen {
+            // Leave the last frame where it is and start the prompt below it.
+            println!();
+        }
+        if res.is_ok() {
+            if let Err(e) = prefs::save(&self.prefs()) {
+                eprintln!("warning: {e:#}");
+            }
+        }
         res
     }
 }
+
+/// Greedily packs `items` into lines of at most `width` columns, joined by `sep`. An
+/// item wider than a line gets one to itself.
+fn wrap_items(items: &[&str], sep: &str, width: usize) -> Vec<String> {
+    let mut lines: Vec<String> = Vec::new();
+    for item in items {
+        match lines.last_mut() {
+            Some(line) if line.chars().count() + sep.len() + item.chars().count() <= width => {
+                line.push_str(sep);
+                line.push_str(item);
+            }
+            _ => lines.push(item.to_string()),
+        }
+    }
+    lines
+}
+
+/// The box a marker note is typed into, centred over the chart.
+fn render_note_input(f: &mut Frame, text: &str) {
+    let screen = f.size();
+    let width = (NOTE_MAX as u16 + 4).min(screen.width);
+    let area = Rect {
+        x: screen.x + (screen.width - width) / 2,
+        y: screen.y + screen.height.saturating_sub(3) / 2,
+        width,
+        height: 3.min(screen.height),
+    };
+    let input = Paragraph::new(format!("{text}_"))
+        .block(Block::default().borders(Borders::ALL).title(" Note (Enter: add, Esc: cancel) "));
+    f.render_widget(Clear, area);
+    f.render_widget(input, area);
+}
+
+fn restore_terminal(altscreen: bool) -> std::io::Result<()> {
+    disable_raw_mode()?;
+    let mut stdout = std::io::stdout();
+    if altscreen {
+        execute!(stdout, LeaveAlternateScreen)?;
+    }
+    execute!(stdout, DisableMouseCapture, cursor::Show)
+}
+
+fn median_of(v: &mut [f64]) -> Option<f64> {
+    if v.is_empty() {
+        return None;
+    }
+    v.sort_by(f64::total_cmp);
+    let mid = v.len() / 2;
+    Some(if v.len().is_multiple_of(2) { (v[mid - 1] + v[mid]) / 2.0 } else { v[mid] })
+}
+
+/// Replaces each RTT with the median of the `k` samples centred on it, for display only.
+/// Losses stay losses and are skipped as neighbours; `k <= 1` leaves the series untouched.
+fn median_filter(rtts: &[Option<f64>], k: usize) -> Vec<Option<f64>> {
+    if k <= 1 {
+        return rtts.to_vec();
+    }
+    let half = k / 2;
+    rtts.iter()
+        .enumerate()
+        .map(|(i, rtt)| {
+            rtt.and_then(|_| {
+                let lo = i.saturating_sub(half);
+                let hi = (i + half + 1).min(rtts.len());
+                median_of(&mut rtts[lo..hi].iter().flatten().copied().collect::<Vec<_>>())
+            })
+        })
+        .collect()
+}
+
+/// Absolute RTT change between each pair of consecutive replies; a loss breaks the chain.
+fn jitter_series(rtts: &[Option<f64>]) -> Vec<(f64, f64)> {
+    rtts.windows(2)
+        .enumerate()
+        .filter_map(|(i, w)| match (w[0], w[1]) {
+            (Some(a), Some(b)) => Some(((i + 1) as f64, (b - a).abs())),
+            _ => None,
+        })
+        .collect()
+}
+
+/// Loss percentage over the `n` samples ending at each position.
+fn rolling_loss(rtts: &[Option<f64>], n: usize) -> Vec<u64> {
+    (0..rtts.len())
+        .map(|i| {
+            let span = &rtts[(i + 1).saturating_sub(n)..=i];
+            let lost = span.iter().filter(|v| v.is_none()).count();
+            (lost * 100 / span.len()) as u64
+        })
+        .collect()
+}
+
+/// Mean and sample stddev of the up to `n` replies before each position; `None` until
+/// there are enough of them for the spread to mean anything.
+fn rolling_band(rtts: &[Option<f64>], n: usize) -> Vec<Option<(f64, f64)>> {
+    (0..rtts.len())
+        .map(|i| {
+            let prev: Vec<f64> = rtts[i.saturating_sub(n)..i].iter().flatten().copied().collect();
+            if prev.len() < MIN_CI_SAMPLES {
+                return None;
+            }
+            let k = prev.len() as f64;
+            let mean = prev.iter().sum::<f64>() / k;
+            let var = prev.iter().map(|v| (v - mean).powi(2)).sum::<f64>() / (k - 1.0);
+            Some((mean, var.sqrt()))
+        })
+        .collect()
+}
+
+/// Reduces the points that land in each column to that column's lowest and highest,
+/// in the order they occurred, so spikes survive while the count stays near two per
+/// column (braille markers can show two points per cell; more is wasted work).
+fn downsample(points: Vec<(f64, f64)>, columns: usize) -> Vec<(f64, f64)> {
+    let per_bucket = points.len().div_ceil(columns.max(1));
+    if per_bucket <= 2 {
+        return points;
+    }
+    points.chunks(per_bucket)
+        .flat_map(|c| {
+            let lo = c.iter().copied().reduce(|a, b| if b.1 < a.1 { b } else { a }).unwrap();
+            let hi = c.iter().copied().reduce(|a, b| if b.1 > a.1 { b } else { a }).unwrap();
+            if lo.0 <= hi.0 { [lo, hi] } else { [hi, lo] }
+        })
+        .collect()
+}
+
+/// `n` evenly spaced values from 0 to `max` inclusive (at least two).
+fn y_ticks(max: f64, n: usize) -> Vec<f64> {
+    let n = n.max(2);
+    (0..n).map(|i| max * i as f64 / (n - 1) as f64).collect()
+}
+
+/// Tick labels without decimals unless the ticks are closer together than 1.
+fn tick_labels(ticks: &[f64]) -> Vec<Span<'static>> {
+    let step = ticks.get(1).copied().unwrap_or_default();
+    let decimals = if step > 0.0 && step < 1.0 { 1 } else { 0 };
+    ticks.iter().map(|v| Span::raw(format!("{v:.decimals$}"))).collect()
+}
+
+fn format_elapsed(d: Duration) -> String {
+    let secs = d.as_secs();
+    format!("{:02}:{:02}:{:02}", secs / 3600, secs / 60 % 60, secs % 60)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample(seq: u64) -> PingSample {
+        let lost = seq.is_multiple_of(7);
+        PingSample {
+            seq,
+            ts: SystemTime::now(),
+            sent: Instant::now(),
+            rtt_ms: (!lost).then_some(seq as f64),
+            error: None,
+            loaded: false,
+            retries: 0,
+            ttl: Some(64),
+            flags: Flags::default(),
+            outcome: if lost { Outcome::Timeout } else { Outcome::Reply },
+        }
+    }
+
+    fn filled(backlog: usize, max_samples: usize, n: u64) -> UiState {
+        let mut state = UiState::new(MIN_WINDOW, backlog);
+        state.set_max_samples(max_samples);
+        for seq in 1..=n {
+            state.push(&sample(seq));
+        }
+        state
+    }
+
+    /// The bookkeeping folding and eviction rely on.
+    fn check(state: &UiState) {
+        assert_eq!(state.rtts.iter().map(|e| e.span).sum::<usize>(), state.samples);
+        assert!(state.samples <= state.backlog);
+        assert!(state.raw <= state.max_raw);
+        assert!(state.raw <= state.rtts.len());
+        assert!(state.rtts.iter().rev().take(state.raw).all(|e| e.span == 1));
+        assert!(state.scroll <= state.max_scroll());
+        assert!(state.rtts.iter().zip(state.rtts.iter().skip(1)).all(|(a, b)| a.seq + a.span as u64 == b.seq));
+    }
+
+    #[test]
+    fn folds_past_max_samples() {
+        let state = filled(1000, 20, 100);
+        check(&state);
+        assert_eq!(state.samples, 100);
+        assert_eq!(state.raw, 20);
+        assert_eq!(state.rtts.len(), 20 + 80 / FOLD);
+        assert_eq!(state.rtts.front().unwrap().span, FOLD);
+    }
+
+    #[test]
+    fn keeps_backlog_samples_when_folded() {
+        let state = filled(50, 20, 500);
+        check(&state);
+        assert!(state.samples > 50 - FOLD);
+        assert_eq!(state.rtts.back().unwrap().seq, 500);
+    }
+
+    #[test]
+    fn shrinking_max_samples_folds_what_is_kept() {
+        let mut state = filled(200, 200, 150);
+        assert_eq!(state.raw, 150);
+        state.set_max_samples(30);
+        check(&state);
+        assert_eq!(state.samples, 150);
+        assert_eq!(state.raw, 30);
+    }
+
+    #[test]
+    fn resize_while_scrolled() {
+        let mut state = filled(400, 40, 400);
+        state.scroll = state.max_scroll();
+        state.resize_backlog(100);
+        check(&state);
+        assert!(state.samples <= 100);
+        state.resize_backlog(25);
+        check(&state);
+        for seq in 401..=450 {
+            state.push(&sample(seq));
+            check(&state);
+        }
+    }
+
+    #[test]
+    fn growing_backlog_is_capped_and_reserves_nothing() {
+        let mut state = filled(400, 40, 400);
+        let capacity = state.rtts.capacity();
+        for _ in 0..80 {
+            state.resize_backlog(state.backlog().saturating_mul(2));
+        }
+        assert_eq!(state.backlog(), MAX_BACKLOG);
+        assert!(state.rtts.capacity() <= capacity);
+        check(&state);
+    }
+
+    #[test]
+    fn visible_seq_counts_folded_samples() {
+        let mut state = filled(1000, 20, 100);
+        state.window = state.rtts.len();
+        assert_eq!(state.visible_seq(), (1, 100));
+        state.window = MIN_WINDOW;
+        state.scroll = state.max_scroll();
+        // Eight folded entries (#1-#80), then the first two single ones.
+        assert_eq!(state.visible_seq(), (1, 82));
+    }
+
+    #[test]
+    fn hints_wrap_to_the_width_with_quit_first() {
+        for width in [30, 60, 100, 140] {
+            let lines = wrap_items(HINTS, "   ", width);
+            assert!(lines[0].starts_with("quit: q"));
+            assert!(lines.iter().all(|l| l.chars().count() <= width), "{width}: {lines:?}");
+            assert_eq!(lines.join("   "), HINTS.join("   "));
+        }
+        assert_eq!(wrap_items(SHORT_HINTS, "  ", 20), ["q  ←→ End  +-", "g j l b s t c v n p m e w d", "H h"]);
+    }
+
+    /// Feeds replies with the given TTLs, one per seq from 1.
+    fn with_ttls(ttls: &[u8]) -> UiState {
+        let mut state = UiState::new(MIN_WINDOW, 100);
+        for (i, &ttl) in ttls.iter().enumerate() {
+            let s = sample(i as u64 + 1);
+            state.push(&PingSample { rtt_ms: Some(1.0), outcome: Outcome::Reply, ttl: Some(ttl), ..s });
+        }
+        state
+    }
+
+    #[test]
+    fn ttl_blip_shorter_than_debounce_is_ignored() {
+        let mut ttls = vec![64; 5];
+        ttls.extend([55; TTL_DEBOUNCE - 1]);
+        ttls.extend([64; 3]);
+        let state = with_ttls(&ttls);
+        assert_eq!(state.ttl, Some(64));
+        assert!(state.events.is_empty());
+        assert!(state.rtts.iter().all(|e| !e.ttl_change));
+    }
+
+    #[test]
+    fn ttl_change_is_marked_where_it_started() {
+        let mut ttls = vec![64; 5];
+        ttls.extend([55; TTL_DEBOUNCE]);
+        let state = with_ttls(&ttls);
+        assert_eq!(state.ttl, Some(55));
+        assert_eq!(state.events.len(), 1);
+        assert!(state.events[0].msg.starts_with("TTL 64 → 55 at #6"));
+        let marked: Vec<u64> = state.rtts.iter().filter(|e| e.ttl_change).map(|e| e.seq).collect();
+        assert_eq!(marked, [6]);
+    }
+}
@@ -0,0 +1,14 @@
+use std::fs;
+use std::os::unix::fs::PermissionsExt;
+use std::path::PathBuf;
+
+/// Writes `script` as an executable `ping` in a fresh directory under the system temp
+/// dir and returns that directory; `name` keeps parallel tests apart.
+pub fn mock_dir(name: &str, script: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("rgping-{name}-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let ping = dir.join("ping");
+    fs::write(&ping, script).unwrap();
+    fs::set_permissions(&ping, fs::Permissions::from_mode(0o755)).unwrap();
+    dir
+}
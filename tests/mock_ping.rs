@@ -0,0 +1,217 @@
+//! Runs rgping against `--ping-bin` scripts that print canned `ping` output, so the
+//! parsing and the probe loop can be checked without touching the network.
+
+#![cfg(unix)]
+
+mod common;
+
+use std::fs;
+use std::process::{Command, Output};
+
+use common::mock_dir;
+
+fn rgping(name: &str, script: &str, args: &[&str]) -> Output {
+    let dir = mock_dir(name, script);
+    let out = Command::new(env!("CARGO_BIN_EXE_rgping"))
+        .arg("--ping-bin")
+        .arg(dir.join("ping"))
+        .args(["--interval-ms", "50", "--timeout-ms", "200"])
+        .args(args)
+        .arg("127.0.0.1")
+        .output()
+        .unwrap();
+    fs::remove_dir_all(&dir).unwrap();
+    out
+}
+
+fn stdout(out: &Output) -> &str {
+    std::str::from_utf8(&out.stdout).unwrap()
+}
+
+const REPLY: &str = "#!/bin/sh
+echo 'PING 127.0.0.1 (127.0.0.1) 56(84) bytes of data.'
+echo '64 bytes from 127.0.0.1: icmp_seq=1 ttl=64 time=12.34 ms'
+echo ''
+echo '--- 127.0.0.1 ping statistics ---'
+echo '1 packets transmitted, 1 received, 0% packet loss, time 0ms'
+echo 'rtt min/avg/max/mdev = 12.340/12.340/12.340/0.000 ms'
+";
+
+#[test]
+fn reply_rtt() {
+    let out = rgping("reply", REPLY, &["--raw", "--count", "2"]);
+    assert!(out.status.success());
+    assert_eq!(stdout(&out), "12.34\n12.34\n");
+}
+
+#[test]
+fn sub_millisecond_reply() {
+    let script = "#!/bin/sh
+echo '64 bytes from 127.0.0.1: icmp_seq=1 ttl=64 time<1ms'
+echo '1 packets transmitted, 1 received, 0% packet loss, time 0ms'
+";
+    let out = rgping("lt1ms", script, &["--raw", "--count", "1"]);
+    assert_eq!(stdout(&out), "1\n");
+}
+
+#[test]
+fn localized_reply() {
+    let script = "#!/bin/sh
+echo 'PING 127.0.0.1 (127.0.0.1) 56(84) Bytes Daten.'
+echo '64 Bytes von 127.0.0.1: icmp_seq=1 ttl=64 Zeit=0,045 ms'
+echo '1 Pakete übertragen, 1 empfangen, 0% Paketverlust, Zeit 0ms'
+";
+    let out = rgping("de", script, &["--raw", "--count", "1"]);
+    assert_eq!(stdout(&out), "0.045\n");
+}
+
+#[test]
+fn duplicate_replies_are_averaged_and_counted() {
+    let script = "#!/bin/sh
+echo '64 bytes from 127.0.0.1: icmp_seq=1 ttl=64 time=10.0 ms'
+echo '64 bytes from 127.0.0.1: icmp_seq=1 ttl=64 time=20.0 ms (DUP!)'
+echo '1 packets transmitted, 1 received, +1 duplicates, 0% packet loss, time 0ms'
+";
+    let out = rgping("dup", script, &["--raw", "--count", "1", "--summary-json", "-"]);
+    let text = stdout(&out);
+    assert!(text.starts_with("15\n"), "{text}");
+    assert!(text.contains("\"duplicates\":1"), "{text}");
+}
+
+#[test]
+fn loss_is_an_empty_line() {
+    let script = "#!/bin/sh
+echo 'PING 127.0.0.1 (127.0.0.1) 56(84) bytes of data.'
+echo '1 packets transmitted, 0 received, 100% packet loss, time 0ms'
+exit 1
+";
+    let out = rgping("loss", script, &["--raw", "--count", "2"]);
+    assert_eq!(stdout(&out), "\n\n");
+}
+
+#[test]
+fn unreachable_reports_reason_and_router() {
+    let script = "#!/bin/sh
+echo 'PING 127.0.0.1 (127.0.0.1) 56(84) bytes of data.'
+echo 'From 10.0.0.1 icmp_seq=1 Destination Host Unreachable'
+echo '1 packets transmitted, 0 received, +1 errors, 100% packet loss, time 0ms'
+exit 1
+";
+    let out = rgping("unreach", script, &["--once"]);
+    assert_eq!(out.status.code(), Some(1));
+    assert_eq!(stdout(&out), "127.0.0.1 127.0.0.1 host unreachable from 10.0.0.1\n");
+}
+
+#[test]
+fn ttl_exceeded() {
+    let script = "#!/bin/sh
+echo 'From 192.168.1.1 icmp_seq=1 Time to live exceeded'
+exit 1
+";
+    let out = rgping("ttlx", script, &["--once"]);
+    assert_eq!(stdout(&out), "127.0.0.1 127.0.0.1 TTL exceeded from 192.168.1.1\n");
+}
+
+#[test]
+fn wait_is_whole_seconds() {
+    // busybox rejects a fractional -W; the 200ms timeout is rounded up to 1.
+    let script = "#!/bin/sh
+case \"$*\" in *'-W 1 '*) ;; *) echo \"ping: invalid -W in $*\" >&2; exit 2;; esac
+echo '64 bytes from 127.0.0.1: icmp_seq=1 ttl=64 time=5 ms'
+";
+    let out = rgping("wait", script, &["--raw", "--count", "1"]);
+    assert_eq!(stdout(&out), "5\n");
+}
+
+#[test]
+fn reply_slower_than_timeout_is_lost() {
+    let script = "#!/bin/sh
+echo '64 bytes from 127.0.0.1: icmp_seq=1 ttl=64 time=250 ms'
+";
+    let out = rgping("slow", script, &["--raw", "--count", "1"]);
+    assert_eq!(stdout(&out), "\n");
+}
+
+#[test]
+fn hang_is_a_timeout() {
+    let out = rgping("hang", "#!/bin/sh\nexec sleep 30\n", &["--once"]);
+    assert_eq!(out.status.code(), Some(1));
+    assert_eq!(stdout(&out), "127.0.0.1 127.0.0.1 timeout\n");
+}
+
+#[test]
+fn ping_failure_is_an_error() {
+    let script = "#!/bin/sh
+echo 'ping: nope.invalid: Name or service not known' >&2
+exit 2
+";
+    let out = rgping("resolve", script, &["--once"]);
+    assert_eq!(out.status.code(), Some(1));
+    assert_eq!(stdout(&out), "127.0.0.1 127.0.0.1 timeout\n");
+    assert!(String::from_utf8_lossy(&out.stderr).contains("error: ping: nope.invalid: Name or service not known"));
+}
+
+#[test]
+fn missing_binary_is_reported() {
+    let out = Command::new(env!("CARGO_BIN_EXE_rgping"))
+        .args(["--ping-bin", "/nonexistent/ping", "--once", "127.0.0.1"])
+        .output()
+        .unwrap();
+    assert_eq!(out.status.code(), Some(1));
+    assert!(String::from_utf8_lossy(&out.stderr).contains("failed to execute `/nonexistent/ping`"));
+}
+
+#[test]
+fn per_probe_resolve_pings_the_address() {
+    // Only answers when handed an address, not the name.
+    let script = "#!/bin/sh
+for a; do host=$a; done
+[ \"$host\" = localhost ] && exit 2
+echo \"64 bytes from $host: icmp_seq=1 ttl=64 time=1.5 ms\"
+";
+    let dir = mock_dir("per_probe", script);
+    let out = Command::new(env!("CARGO_BIN_EXE_rgping"))
+        .arg("--ping-bin")
+        .arg(dir.join("ping"))
+        .args(["--interval-ms", "50", "--resolve", "per-probe", "--raw", "--count", "2", "localhost"])
+        .output()
+        .unwrap();
+    fs::remove_dir_all(&dir).unwrap();
+    assert_eq!(stdout(&out), "1.5\n1.5\n");
+}
+
+#[test]
+fn once_prints_the_address_pinged() {
+    // Records the address it was given next to itself.
+    let script = "#!/bin/sh
+for a; do host=$a; done
+echo \"$host\" > \"$(dirname \"$0\")/target\"
+echo \"64 bytes from $host: icmp_seq=1 ttl=64 time=1.5 ms\"
+";
+    let dir = mock_dir("once_addr", script);
+    let out = Command::new(env!("CARGO_BIN_EXE_rgping"))
+        .arg("--ping-bin")
+        .arg(dir.join("ping"))
+        .args(["--once", "localhost"])
+        .output()
+        .unwrap();
+    let target = fs::read_to_string(dir.join("target")).unwrap();
+    fs::remove_dir_all(&dir).unwrap();
+    assert_eq!(stdout(&out), format!("localhost {} 1.5ms\n", target.trim()));
+}
+
+#[test]
+fn separate_unreachable_is_not_loss_in_the_summary() {
+    let script = "#!/bin/sh
+echo 'From 10.0.0.1 icmp_seq=1 Destination Host Unreachable'
+exit 1
+";
+    let json = |mode: &str| {
+        let out = rgping(&format!("unreach_{mode}"), script, &["--once", "--unreachable-as", mode, "--summary-json", "-"]);
+        stdout(&out).lines().nth(1).unwrap().to_string()
+    };
+    let separate = json("separate");
+    assert!(separate.contains("\"loss\":0.000,"), "{separate}");
+    assert!(separate.contains("\"unreachable\":1,"), "{separate}");
+    assert!(json("loss").contains("\"loss\":100.000,"));
+}
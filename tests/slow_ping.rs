@@ -0,0 +1,68 @@
+//! A `ping` that never answers must not stall the sample stream.
+
+#![cfg(unix)]
+
+mod common;
+
+use std::fs;
+use std::process::{Command, Stdio};
+use std::thread::sleep;
+use std::time::{Duration, Instant};
+
+use common::mock_dir;
+
+#[test]
+fn hung_ping_is_killed_and_counted_as_loss() {
+    let dir = mock_dir("hung", "#!/bin/sh\nexec sleep 30\n");
+    let path = format!("{}:{}", dir.display(), std::env::var("PATH").unwrap_or_default());
+
+    let started = Instant::now();
+    let mut child = Command::new(env!("CARGO_BIN_EXE_rgping"))
+        .args(["--raw", "--count", "3", "--interval-ms", "100", "--timeout-ms", "100", "127.0.0.1"])
+        .env("PATH", path)
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
+        .unwrap();
+
+    // Each probe should give up after its timeout, well under 30s.
+    let status = loop {
+        if let Some(status) = child.try_wait().unwrap() {
+            break status;
+        }
+        if started.elapsed() > Duration::from_secs(10) {
+            child.kill().unwrap();
+            panic!("rgping still running after 10s; the hung ping was not killed");
+        }
+        sleep(Duration::from_millis(50));
+    };
+    let out = child.wait_with_output().unwrap();
+    fs::remove_dir_all(&dir).unwrap();
+
+    assert!(status.success());
+    assert_eq!(String::from_utf8(out.stdout).unwrap(), "\n\n\n");
+}
+
+#[test]
+fn retries_stay_within_the_interval() {
+    let dir = mock_dir("hung_retry", "#!/bin/sh\nexec sleep 30\n");
+
+    // A 500ms interval has room for a 200ms probe and one 200ms retry; a second
+    // retry would run past it.
+    let started = Instant::now();
+    let out = Command::new(env!("CARGO_BIN_EXE_rgping"))
+        .arg("--ping-bin")
+        .arg(dir.join("ping"))
+        .args(["--raw", "--count", "3", "--interval-ms", "500", "--timeout-ms", "200", "--retries", "5", "127.0.0.1"])
+        .stderr(Stdio::null())
+        .output()
+        .unwrap();
+    let took = started.elapsed();
+    fs::remove_dir_all(&dir).unwrap();
+
+    assert!(out.status.success());
+    assert_eq!(String::from_utf8(out.stdout).unwrap(), "\n\n\n");
+    // Two full intervals plus the last probe's two attempts, about 1.4s; retrying
+    // while any of the interval is left would take 1.8s.
+    assert!(took < Duration::from_millis(1650), "took {took:?}");
+}